@@ -0,0 +1,412 @@
+// Formula waveforms, e.g. "sin(2*pi*p) * 0.5 + tri(p)*0.5", where `p` is the phase in 0.0..1.0.
+//
+// Grammar, loosest binding first:
+//   expr  := term (('+' | '-') term)*
+//   term  := unary (('*' | '/' | '%') unary)*
+//   unary := '-' unary | power
+//   power := atom ('^' unary)?
+//   atom  := number | name | name '(' expr (',' expr)* ')' | '(' expr ')'
+use std::f64::consts::{E, PI, TAU};
+use std::fmt;
+
+use crate::{pulse, saw, triangle, Waveform};
+
+// how deeply a formula may nest, counting brackets, calls, signs and chained operators, so a
+// runaway formula is rejected instead of overflowing the stack
+const MAX_DEPTH: usize = 256;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub position: usize, // byte offset into the formula
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Func {
+    Sin,
+    Cos,
+    Tan,
+    Abs,
+    Sqrt,
+    Exp,
+    Ln,
+    Floor,
+    Fract,
+    Min,
+    Max,
+    Tri,
+    Saw,
+    Pulse,
+}
+
+impl Func {
+    fn lookup(name: &str) -> Option<(Func, usize)> {
+        let func = match name {
+            "sin" => (Func::Sin, 1),
+            "cos" => (Func::Cos, 1),
+            "tan" => (Func::Tan, 1),
+            "abs" => (Func::Abs, 1),
+            "sqrt" => (Func::Sqrt, 1),
+            "exp" => (Func::Exp, 1),
+            "ln" => (Func::Ln, 1),
+            "floor" => (Func::Floor, 1),
+            "fract" => (Func::Fract, 1),
+            "min" => (Func::Min, 2),
+            "max" => (Func::Max, 2),
+            "tri" => (Func::Tri, 1),
+            "saw" => (Func::Saw, 1),
+            "pulse" => (Func::Pulse, 2),
+            _ => return None,
+        };
+        Some(func)
+    }
+
+    fn apply(self, args: &[f64]) -> f64 {
+        // the shape helpers expect a phase in 0.0..1.0
+        let wrap = |x: f64| x.rem_euclid(1.0);
+        match self {
+            Func::Sin => args[0].sin(),
+            Func::Cos => args[0].cos(),
+            Func::Tan => args[0].tan(),
+            Func::Abs => args[0].abs(),
+            Func::Sqrt => args[0].sqrt(),
+            Func::Exp => args[0].exp(),
+            Func::Ln => args[0].ln(),
+            Func::Floor => args[0].floor(),
+            Func::Fract => wrap(args[0]),
+            Func::Min => args[0].min(args[1]),
+            Func::Max => args[0].max(args[1]),
+            Func::Tri => triangle(wrap(args[0])),
+            Func::Saw => saw(wrap(args[0]), true),
+            Func::Pulse => pulse(wrap(args[0]), args[1]),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Num(f64),
+    Phase,
+    Neg(Box<Node>),
+    Bin(Op, Box<Node>, Box<Node>),
+    Call(Func, Vec<Node>),
+}
+
+impl Node {
+    fn eval(&self, phase: f64) -> f64 {
+        match self {
+            Node::Num(x) => *x,
+            Node::Phase => phase,
+            Node::Neg(a) => -a.eval(phase),
+            Node::Bin(op, a, b) => {
+                let (a, b) = (a.eval(phase), b.eval(phase));
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                    Op::Rem => a % b,
+                    Op::Pow => a.powf(b),
+                }
+            },
+            Node::Call(func, args) => {
+                // no function takes more than two arguments, and this runs on the audio thread
+                let mut values = [0.0; 2];
+                for (value, arg) in values.iter_mut().zip(args) {
+                    *value = arg.eval(phase);
+                }
+                func.apply(&values[..args.len()])
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    root: Node,
+}
+
+impl Expr {
+    pub fn parse(formula: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser { src: formula.as_bytes(), pos: 0, depth: 0 };
+        let root = parser.expr()?;
+        parser.skip_space();
+        if parser.pos < parser.src.len() {
+            return Err(parser.error("unexpected character"));
+        }
+        Ok(Expr { root })
+    }
+
+    pub fn eval(&self, phase: f64) -> f64 {
+        self.root.eval(phase)
+    }
+}
+
+impl Waveform {
+    // the result is clamped to -1.0..1.0 like every other waveform
+    pub fn from_formula(formula: &str) -> Result<Self, ParseError> {
+        let expr = Expr::parse(formula)?;
+        Ok(Waveform::Custom(Box::new(move |phase| {
+            let value = expr.eval(phase);
+            if value.is_nan() {
+                0.0
+            } else {
+                value.clamp(-1.0, 1.0)
+            }
+        })))
+    }
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> ParseError {
+        ParseError { position: self.pos, message: message.to_string() }
+    }
+
+    fn skip_space(&mut self) {
+        while self.src.get(self.pos).is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_space();
+        self.src.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn nest(&mut self) -> Result<(), ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("formula nested too deeply"));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    // each operator in a chain nests the tree one level deeper
+    fn expr(&mut self) -> Result<Node, ParseError> {
+        let depth = self.depth;
+        let mut node = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Some(b'+') => Op::Add,
+                Some(b'-') => Op::Sub,
+                _ => {
+                    self.depth = depth;
+                    return Ok(node);
+                },
+            };
+            self.nest()?;
+            self.pos += 1;
+            node = Node::Bin(op, Box::new(node), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Node, ParseError> {
+        let depth = self.depth;
+        let mut node = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(b'*') => Op::Mul,
+                Some(b'/') => Op::Div,
+                Some(b'%') => Op::Rem,
+                _ => {
+                    self.depth = depth;
+                    return Ok(node);
+                },
+            };
+            self.nest()?;
+            self.pos += 1;
+            node = Node::Bin(op, Box::new(node), Box::new(self.unary()?));
+        }
+    }
+
+    // every way of nesting comes back through here
+    fn unary(&mut self) -> Result<Node, ParseError> {
+        self.nest()?;
+        let node = if self.eat(b'-') {
+            Node::Neg(Box::new(self.unary()?))
+        } else {
+            self.power()?
+        };
+        self.depth -= 1;
+        Ok(node)
+    }
+
+    fn power(&mut self) -> Result<Node, ParseError> {
+        let base = self.atom()?;
+        if self.eat(b'^') {
+            // right associative: 2^3^2 == 2^(3^2)
+            Ok(Node::Bin(Op::Pow, Box::new(base), Box::new(self.unary()?)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn atom(&mut self) -> Result<Node, ParseError> {
+        match self.peek() {
+            Some(b'(') => {
+                self.pos += 1;
+                let node = self.expr()?;
+                if !self.eat(b')') {
+                    return Err(self.error("expected ')'"));
+                }
+                Ok(node)
+            },
+            Some(c) if c.is_ascii_digit() || c == b'.' => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => self.name(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of formula")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Node, ParseError> {
+        let start = self.pos;
+        while self.src.get(self.pos).is_some_and(|c| c.is_ascii_digit() || *c == b'.') {
+            self.pos += 1;
+        }
+        if matches!(self.src.get(self.pos), Some(b'e' | b'E')) {
+            let mantissa_end = self.pos;
+            self.pos += 1;
+            if matches!(self.src.get(self.pos), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !self.src.get(self.pos).is_some_and(|c| c.is_ascii_digit()) {
+                // not an exponent after all, e.g. "2e" is left for the caller to reject
+                self.pos = mantissa_end;
+            }
+            while self.src.get(self.pos).is_some_and(|c| c.is_ascii_digit()) {
+                self.pos += 1;
+            }
+        }
+        let text = std::str::from_utf8(&self.src[start..self.pos]).unwrap_or_default();
+        text.parse().map(Node::Num).map_err(|_| ParseError {
+            position: start,
+            message: format!("invalid number '{}'", text),
+        })
+    }
+
+    fn name(&mut self) -> Result<Node, ParseError> {
+        let start = self.pos;
+        while self.src.get(self.pos).is_some_and(|c| c.is_ascii_alphanumeric() || *c == b'_') {
+            self.pos += 1;
+        }
+        let name = std::str::from_utf8(&self.src[start..self.pos]).unwrap_or_default();
+        if self.peek() != Some(b'(') {
+            return match name {
+                "p" => Ok(Node::Phase),
+                "pi" => Ok(Node::Num(PI)),
+                "tau" => Ok(Node::Num(TAU)),
+                "e" => Ok(Node::Num(E)),
+                _ => Err(ParseError { position: start, message: format!("unknown variable '{}'", name) }),
+            };
+        }
+        let (func, arity) = Func::lookup(name).ok_or_else(|| ParseError {
+            position: start,
+            message: format!("unknown function '{}'", name),
+        })?;
+        self.pos += 1;
+        let mut args = vec![self.expr()?];
+        while self.eat(b',') {
+            args.push(self.expr()?);
+        }
+        if !self.eat(b')') {
+            return Err(self.error("expected ')'"));
+        }
+        if args.len() != arity {
+            return Err(ParseError {
+                position: start,
+                message: format!("'{}' takes {} argument(s), got {}", name, arity, args.len()),
+            });
+        }
+        Ok(Node::Call(func, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_with_precedence() {
+        let expr = Expr::parse("1 + 2 * 3 ^ 2 - -4 / 2").unwrap();
+        assert_eq!(expr.eval(0.0), 21.0);
+        assert_eq!(Expr::parse("2^3^2").unwrap().eval(0.0), 512.0);
+        assert_eq!(Expr::parse("(1 + 2) * p").unwrap().eval(0.5), 1.5);
+        assert_eq!(Expr::parse("1.5e1 + .5").unwrap().eval(0.0), 15.5);
+    }
+
+    #[test]
+    fn evaluates_functions() {
+        let expr = Expr::parse("sin(2*pi*p) * 0.5 + tri(p)*0.5").unwrap();
+        assert!((expr.eval(0.25) - 0.5).abs() < 1e-12);
+        assert_eq!(Expr::parse("max(p, 0.3)").unwrap().eval(0.1), 0.3);
+        assert_eq!(Expr::parse("pulse(p, 0.25)").unwrap().eval(0.5), -1.0);
+        assert_eq!(Expr::parse("saw(p + 1)").unwrap().eval(0.5), 0.0);
+        let expr = Expr::parse("min(max(p, 0.2), pulse(p, 0.5) + 0.6) - max(min(p, 1), 0)").unwrap();
+        assert!((expr.eval(0.1) - 0.1).abs() < 1e-12);
+        assert!((expr.eval(0.75) - -1.15).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rejects_formulas_nested_too_deeply() {
+        let nested = |n: usize| format!("{}p{}", "(".repeat(n), ")".repeat(n));
+        assert!(Expr::parse(&nested(100)).is_ok());
+        let err = Expr::parse(&nested(100_000)).unwrap_err();
+        assert_eq!(err.message, "formula nested too deeply");
+        assert!(Expr::parse(&"-".repeat(100_000)).is_err());
+        assert!(Expr::parse(&vec!["p"; 100_000].join("+")).is_err());
+        assert!(Expr::parse(&vec!["max(p, 0)"; 100].join("*")).is_ok());
+    }
+
+    #[test]
+    fn reports_errors_with_position() {
+        let err = Expr::parse("sin(p").unwrap_err();
+        assert_eq!(err.position, 5);
+        assert_eq!(Expr::parse("foo(p)").unwrap_err().position, 0);
+        assert_eq!(Expr::parse("1 + q").unwrap_err().position, 4);
+        assert!(Expr::parse("min(p)").is_err());
+        assert!(Expr::parse("1 2").is_err());
+        assert!(Expr::parse("").is_err());
+    }
+
+    #[test]
+    fn formula_waveform_is_clamped() {
+        use dasp_signal::Signal;
+        let mut lfo = crate::LFO::new(Waveform::from_formula("3 * sin(tau * p)").unwrap(), 1.0, 4.0);
+        let out: Vec<f64> = (0..4).map(|_| lfo.next()).collect();
+        assert_eq!(out[1], 1.0);
+        assert_eq!(out[3], 0.0);
+    }
+}
@@ -0,0 +1,136 @@
+// Following JACK transport: the LFO takes the transport tempo and derives its phase from the
+// transport position, so it lines up with every other transport-aware client.
+use jack::{Frames, Transport, TransportBBT, TransportState};
+
+use crate::LFO;
+
+// jack's bpm and bar/beat/tick are in units of the time signature's beat type
+const QUARTER: f64 = 4.0;
+
+// call sync() at the top of every process callback; a relocation (anything other than the frame
+// after the last block) restarts the LFO's cycle on the new position, and the output holds while
+// the transport is stopped
+#[derive(Default)]
+pub struct JackTransport {
+    next_frame: Option<Frames>, // where the transport should be at the next block if nobody seeks
+}
+
+impl JackTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // transport errors, e.g. a client that has already been closed, leave the LFO untouched
+    pub fn sync(&mut self, lfo: &mut LFO, transport: &Transport, n_frames: Frames) {
+        if let Ok(status) = transport.query() {
+            let frame = status.pos.frame();
+            let seconds = status.pos.frame_rate().map(|rate| frame as f64 / rate as f64);
+            self.follow(lfo, status.state, frame, seconds, status.pos.bbt(), n_frames);
+        }
+    }
+
+    fn follow(
+        &mut self,
+        lfo: &mut LFO,
+        state: TransportState,
+        frame: Frames,
+        seconds: Option<f64>,
+        bbt: Option<TransportBBT>,
+        n_frames: Frames,
+    ) {
+        let rolling = state == TransportState::Rolling;
+        if self.next_frame.is_some_and(|next| next != frame) {
+            // only the phase: a seek shouldn't re-arm the start delay or fade-in
+            lfo.set_phase(0.0);
+        }
+        lfo.set_transport_stopped(!rolling);
+        self.next_frame = Some(if rolling { frame.wrapping_add(n_frames) } else { frame });
+
+        if let Some(bbt) = bbt {
+            lfo.set_tempo(bbt.bpm * QUARTER / bbt.sig_denom as f64);
+        }
+        // without bar/beat/tick from a timebase master, the position comes from the frame count
+        let position = match (bbt, seconds, lfo.tempo) {
+            (Some(bbt), _, _) => {
+                let beats = (bbt.bar - 1) as f64 * bbt.sig_num as f64
+                    + (bbt.beat - 1) as f64
+                    + bbt.tick as f64 / bbt.ticks_per_beat;
+                beats * QUARTER / bbt.sig_denom as f64
+            },
+            (None, Some(seconds), Some(bpm)) => seconds * bpm / 60.0,
+            _ => return,
+        };
+        let error = lfo.beat_error(position);
+        lfo.nudge_phase(error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Waveform;
+    use crate::tempo::NoteValue;
+    use dasp_signal::Signal;
+
+    fn bbt(bar: usize, beat: usize, tick: usize) -> TransportBBT {
+        TransportBBT {
+            bar,
+            beat,
+            tick,
+            sig_num: 4.0,
+            sig_denom: 4.0,
+            ticks_per_beat: 1920.0,
+            bpm: 120.0,
+            bar_start_tick: 0.0,
+        }
+    }
+
+    #[test]
+    fn phase_comes_from_bar_beat_tick() {
+        let mut transport = JackTransport::new();
+        let mut lfo = LFO::new(Waveform::SawUp, 0.3, 48000.0);
+        lfo.set_rate_division(NoteValue::Whole);
+        transport.follow(&mut lfo, TransportState::Rolling, 0, None, Some(bbt(3, 2, 960)), 256);
+        assert_eq!(lfo.freq, 0.5);
+        assert!((lfo.phase() - 0.375).abs() < 1e-9);
+
+        // an eighth note in 6/8 is the beat
+        let mut eighths = bbt(1, 4, 0);
+        eighths.sig_num = 6.0;
+        eighths.sig_denom = 8.0;
+        transport.follow(&mut lfo, TransportState::Rolling, 256, None, Some(eighths), 256);
+        assert_eq!(lfo.freq, 0.25);
+        assert!((lfo.phase() - 0.375).abs() < 1e-9);
+    }
+
+    #[test]
+    fn relocation_moves_the_phase_and_stopping_holds() {
+        let mut transport = JackTransport::new();
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 1000.0);
+        lfo.set_tempo(60.0);
+        lfo.set_start_delay(0.5, 1.0);
+        transport.follow(&mut lfo, TransportState::Rolling, 0, Some(0.0), None, 1000);
+        for _ in 0..1000 {
+            lfo.next();
+        }
+        // a seek back to the start lands on the new position without waiting out the delay
+        transport.follow(&mut lfo, TransportState::Rolling, 0, Some(0.0), None, 100);
+        assert_eq!(lfo.next(), 0.0);
+
+        transport.follow(&mut lfo, TransportState::Stopped, 100, Some(0.1), None, 100);
+        let held = lfo.next();
+        transport.follow(&mut lfo, TransportState::Stopped, 100, Some(0.1), None, 100);
+        assert_eq!(lfo.next(), held);
+        transport.follow(&mut lfo, TransportState::Rolling, 100, Some(0.1), None, 100);
+        assert!((lfo.phase() - 0.1).abs() < 1e-9);
+        assert_ne!(lfo.next(), held);
+
+        // a freeze of the user's own outlasts the transport stopping and rolling again
+        lfo.freeze();
+        let frozen = lfo.next();
+        transport.follow(&mut lfo, TransportState::Stopped, 200, Some(0.2), None, 100);
+        transport.follow(&mut lfo, TransportState::Rolling, 200, Some(0.2), None, 100);
+        assert!(lfo.is_frozen());
+        assert_eq!(lfo.next(), frozen);
+    }
+}
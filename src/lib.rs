@@ -1,8 +1,31 @@
 use std::f64::consts::TAU;
-use dasp_signal::Signal;
+use dasp_signal::{self as signal, Noise, Signal};
 
-fn phase(freq: f64, time: f64, theta: f64) -> f64 {
-    (freq * time + theta).fract()
+use scale::Scale;
+use tempo::{Division, NoteValue};
+
+pub mod presets;
+pub mod scale;
+pub mod tempo;
+#[cfg(feature = "expr")]
+pub mod expr;
+#[cfg(feature = "hound")]
+pub mod wav;
+#[cfg(feature = "midi")]
+pub mod midi;
+#[cfg(feature = "rusty_link")]
+pub mod link;
+#[cfg(feature = "jack")]
+pub mod jack_transport;
+
+fn phase(accumulated: f64, theta: f64) -> f64 {
+    (accumulated + theta).fract()
+}
+
+// like rem_euclid(1.0), which can round a tiny negative up to exactly 1.0
+fn wrap(phase: f64) -> f64 {
+    let phase = phase.rem_euclid(1.0);
+    if phase < 1.0 { phase } else { 0.0 }
 }
 
 fn sine(phase: f64) -> f64 {
@@ -33,157 +56,3823 @@ fn pulse(phase: f64, duty_ratio: f64) -> f64 {
     }
 }
 
+// exponential decay from 1.0 to -1.0 over the cycle, `tau` in cycles
+fn exp_saw(phase: f64, tau: f64) -> f64 {
+    let tau = tau.max(1e-6);
+    let end = (-1.0 / tau).exp();
+    2.0 * ((-phase / tau).exp() - end) / (1.0 - end) - 1.0
+}
+
+fn sine_half(phase: f64) -> f64 {
+    2.0 * sine(phase).max(0.0) - 1.0
+}
+
+fn sine_full_rect(phase: f64) -> f64 {
+    // one rectified bump per cycle
+    2.0 * (0.5 * TAU * phase).sin() - 1.0
+}
+
+fn sine_quarter(phase: f64) -> f64 {
+    2.0 * (0.25 * TAU * phase).sin() - 1.0
+}
+
+// sign-preserving sin^n: n < 1 squares the shape up, n > 1 narrows it
+fn power_sine(phase: f64, exponent: f64) -> f64 {
+    let s = sine(phase);
+    s.signum() * s.abs().powf(exponent.max(0.0))
+}
+
+// rise, high plateau, fall, low plateau; rise/fall are fractions of the cycle
+fn trapezoid(phase: f64, rise: f64, fall: f64) -> f64 {
+    let rise = rise.clamp(0.0, 1.0);
+    let fall = fall.clamp(0.0, 1.0 - rise);
+    let hold = 0.5 * (1.0 - rise - fall);
+    if phase < rise {
+        2.0 * phase / rise - 1.0
+    } else if phase < rise + hold {
+        1.0
+    } else if phase < rise + hold + fall {
+        1.0 - 2.0 * (phase - rise - hold) / fall
+    } else {
+        -1.0
+    }
+}
+
+fn stairs(phase: f64, steps: u32, base: StairBase) -> f64 {
+    let n = steps.max(2);
+    let i = ((phase * n as f64) as u32).min(n - 1);
+    let level = 2.0 * i as f64 / (n - 1) as f64 - 1.0;
+    match base {
+        StairBase::Triangle => triangle(i as f64 / n as f64),
+        StairBase::SawUp => level,
+        StairBase::SawDn => -level,
+    }
+}
+
+// equal-length steps; `glide` is the fraction of each step spent sliding into the next
+fn step_seq(phase: f64, levels: &[f64], glide: f64) -> f64 {
+    if levels.is_empty() {
+        return 0.0;
+    }
+    let n = levels.len();
+    let pos = phase * n as f64;
+    let i = (pos as usize).min(n - 1);
+    let frac = pos - i as f64;
+    let glide = glide.clamp(0.0, 1.0);
+    let current = levels[i];
+    if glide == 0.0 || frac < 1.0 - glide {
+        current
+    } else {
+        let t = (frac - (1.0 - glide)) / glide;
+        current + (levels[(i + 1) % n] - current) * t
+    }
+}
+
+// `pulses` onsets spread as evenly as possible over `steps`, rotated left by `rotation`
+fn euclid(phase: f64, pulses: u32, steps: u32, rotation: u32) -> f64 {
+    let steps = steps.max(1) as u64;
+    let pulses = (pulses as u64).min(steps);
+    let i = ((phase * steps as f64) as u64).min(steps - 1);
+    let j = (i + rotation as u64) % steps;
+    if (j * pulses) % steps < pulses {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+// (amplitude, phase in cycles) of harmonics 1, 2, 3, ..., normalized to +-1.0
+fn harmonics(phase: f64, partials: &[(f64, f64)]) -> f64 {
+    let norm: f64 = partials.iter().map(|(amp, _)| amp.abs()).sum();
+    if norm == 0.0 {
+        return 0.0;
+    }
+    partials.iter().enumerate().map(|(k, (amp, offset))| {
+        amp * sine((k + 1) as f64 * phase + offset)
+    }).sum::<f64>() / norm
+}
+
+// one cycle of samples, read cyclically so the last sample interpolates into the first
+fn wavetable(phase: f64, table: &[f64], interpolation: Interpolation) -> f64 {
+    let n = table.len();
+    if n == 0 {
+        return 0.0;
+    }
+    let pos = phase * n as f64;
+    let i = (pos as usize).min(n - 1);
+    let at = |offset: usize| table[(i + offset) % n];
+    let points = [at(n - 1), at(0), at(1), at(2)];
+    interpolate(&points, pos - i as f64, interpolation)
+}
+
+// crossfade between the two tables either side of `morph`
+fn wavetable_bank(phase: f64, tables: &[Vec<f64>], morph: f64, interpolation: Interpolation) -> f64 {
+    if tables.is_empty() {
+        return 0.0;
+    }
+    let pos = morph.clamp(0.0, 1.0) * (tables.len() - 1) as f64;
+    let i = (pos as usize).min(tables.len() - 1);
+    let a = wavetable(phase, &tables[i], interpolation);
+    match tables.get(i + 1) {
+        Some(next) => a + (wavetable(phase, next, interpolation) - a) * (pos - i as f64),
+        None => a,
+    }
+}
+
+// warps 0..1 onto itself: 0.0 is linear, positive bends towards exponential, negative towards logarithmic
+fn bend(t: f64, curve: f64) -> f64 {
+    let k = 8.0 * curve.clamp(-1.0, 1.0);
+    if k.abs() < 1e-6 {
+        t
+    } else {
+        (k * t).exp_m1() / k.exp_m1()
+    }
+}
+
+// breakpoints must be sorted by time; the last one joins back to the first across the cycle end
+fn segments(phase: f64, points: &[Breakpoint]) -> f64 {
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return 0.0,
+    };
+    let next = points.iter().position(|p| p.time > phase);
+    let (a, b, a_time, b_time) = match next {
+        Some(0) => (last, first, last.time - 1.0, first.time),
+        Some(i) => (&points[i - 1], &points[i], points[i - 1].time, points[i].time),
+        None => (last, first, last.time, first.time + 1.0),
+    };
+    let span = b_time - a_time;
+    if span <= 0.0 {
+        return b.value;
+    }
+    let t = bend((phase - a_time) / span, a.curve);
+    a.value + (b.value - a.value) * t
+}
+
+// closed Catmull-Rom curve through (time, value) control points sorted by time
+fn spline(phase: f64, points: &[(f64, f64)]) -> f64 {
+    let n = points.len();
+    if n == 0 {
+        return 0.0;
+    }
+    let at = |i: usize| points[i % n].1;
+    let next = points.iter().position(|(time, _)| *time > phase);
+    // segment i runs from point i to point i + 1, wrapping past the cycle end
+    let (i, start, end) = match next {
+        Some(0) => (n - 1, points[n - 1].0 - 1.0, points[0].0),
+        Some(i) => (i - 1, points[i - 1].0, points[i].0),
+        None => (n - 1, points[n - 1].0, points[0].0 + 1.0),
+    };
+    let span = end - start;
+    if span <= 0.0 {
+        return at(i + 1);
+    }
+    let controls = [at(i + n - 1), at(i), at(i + 1), at(i + 2)];
+    interpolate(&controls, (phase - start) / span, Interpolation::Cubic)
+}
+
+// one smooth bump centred in the cycle, `width` a fraction of the cycle
+fn bump(phase: f64, width: f64, shape: BumpShape) -> f64 {
+    let width = width.clamp(1e-6, 1.0);
+    let d = phase - 0.5;
+    let level = match shape {
+        BumpShape::RaisedCosine if d.abs() < 0.5 * width => 0.5 * (1.0 + (TAU * d / width).cos()),
+        BumpShape::RaisedCosine => 0.0,
+        BumpShape::Gaussian => {
+            // +-3 sigma spans the width
+            let sigma = width / 6.0;
+            (-d * d / (2.0 * sigma * sigma)).exp()
+        },
+    };
+    2.0 * level - 1.0
+}
+
+// `bounces` parabolic arcs; each arc is `damping` times as long and damping^2 as high as the last
+fn bouncing_ball(phase: f64, bounces: u32, damping: f64) -> f64 {
+    let n = bounces.max(1);
+    let r = damping.clamp(0.0, 1.0);
+    let total: f64 = (0..n).map(|k| r.powi(k as i32)).sum();
+    let mut start = 0.0;
+    for k in 0..n {
+        let length = r.powi(k as i32) / total;
+        if phase < start + length || k == n - 1 {
+            let u = ((phase - start) / length).clamp(0.0, 1.0);
+            let height = r.powi(2 * k as i32) * 4.0 * u * (1.0 - u);
+            return 2.0 * height - 1.0;
+        }
+        start += length;
+    }
+    -1.0
+}
+
+// rises for `symmetry` of the cycle then falls: 0.0 is SawDn, 0.5 Triangle, 1.0 SawUp
+fn skew_triangle(phase: f64, symmetry: f64) -> f64 {
+    let s = symmetry.clamp(0.0, 1.0);
+    if phase < s {
+        2.0 * phase / s - 1.0
+    } else {
+        1.0 - 2.0 * (phase - s) / (1.0 - s)
+    }
+}
+
+fn curved_saw(phase: f64, curve: f64, ramp_up: bool) -> f64 {
+    saw(bend(phase, curve), ramp_up)
+}
+
+// the lowest `length` bits, read most significant first so 0b1011 reads left to right
+fn pattern(phase: f64, bits: u32, length: u8) -> f64 {
+    let length = length.clamp(1, 32) as u32;
+    let i = ((phase * length as f64) as u32).min(length - 1);
+    if bits >> (length - 1 - i) & 1 == 1 {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+// a parabolic arc per half-cycle, in phase with sine
+fn parabolic(phase: f64) -> f64 {
+    if phase < 0.5 {
+        16.0 * phase * (0.5 - phase)
+    } else {
+        -16.0 * (phase - 0.5) * (1.0 - phase)
+    }
+}
+
+// sine whose frequency sweeps linearly from `start` to `end` times the LFO rate over each cycle;
+// it only joins up smoothly at the cycle end when start + end is even
+fn chirp(phase: f64, start: f64, end: f64) -> f64 {
+    sine(start * phase + 0.5 * (end - start) * phase * phase)
+}
+
+fn ease(t: f64, easing: Easing) -> f64 {
+    match easing {
+        Easing::InQuad => t * t,
+        Easing::OutQuad => 1.0 - (1.0 - t) * (1.0 - t),
+        Easing::InOutQuad if t < 0.5 => 2.0 * t * t,
+        Easing::InOutQuad => 1.0 - 2.0 * (1.0 - t) * (1.0 - t),
+        Easing::InCubic => t * t * t,
+        Easing::OutCubic => 1.0 - (1.0 - t).powi(3),
+        Easing::InOutCubic if t < 0.5 => 4.0 * t * t * t,
+        Easing::InOutCubic => 1.0 - 4.0 * (1.0 - t).powi(3),
+        Easing::InOutSine => 0.5 * (1.0 - (0.5 * TAU * t).cos()),
+        Easing::InExpo if t == 0.0 => 0.0,
+        Easing::InExpo => 2f64.powf(10.0 * t - 10.0),
+        Easing::OutExpo if t == 1.0 => 1.0,
+        Easing::OutExpo => 1.0 - 2f64.powf(-10.0 * t),
+        Easing::OutElastic if t == 0.0 || t == 1.0 => t,
+        Easing::OutElastic => 2f64.powf(-10.0 * t) * ((10.0 * t - 0.75) * TAU / 3.0).sin() + 1.0,
+        Easing::OutBounce => {
+            const N: f64 = 7.5625;
+            const D: f64 = 2.75;
+            if t < 1.0 / D {
+                N * t * t
+            } else if t < 2.0 / D {
+                let t = t - 1.5 / D;
+                N * t * t + 0.75
+            } else if t < 2.5 / D {
+                let t = t - 2.25 / D;
+                N * t * t + 0.9375
+            } else {
+                let t = t - 2.625 / D;
+                N * t * t + 0.984375
+            }
+        },
+    }
+}
+
+// eases up over the first half of the cycle and mirrors back down over the second;
+// OutElastic overshoots past 1.0 on the way
+fn eased(phase: f64, easing: Easing) -> f64 {
+    let t = if phase < 0.5 { 2.0 * phase } else { 2.0 * (1.0 - phase) };
+    2.0 * ease(t, easing) - 1.0
+}
+
+// the result is kept inside 0.0..1.0 whatever a custom warp returns
+fn time_warp(phase: f64, warp: PhaseWarp) -> f64 {
+    let warped = match warp {
+        PhaseWarp::Exponential(curve) => bend(phase, curve),
+        PhaseWarp::Sigmoid(steepness) => {
+            let a = 12.0 * steepness.clamp(0.0, 1.0);
+            if a < 1e-6 {
+                phase
+            } else {
+                let s = |x: f64| 1.0 / (1.0 + (-a * (x - 0.5)).exp());
+                (s(phase) - s(0.0)) / (s(1.0) - s(0.0))
+            }
+        },
+        PhaseWarp::Custom(f) => f(phase),
+    };
+    if warped.is_nan() {
+        phase
+    } else {
+        warped.clamp(0.0, 1.0 - f64::EPSILON)
+    }
+}
+
+// time within the cycle onto waveform phase, standing still for `length` at each `(phase, length)`
+// hold, the moving parts sped up to keep the period; holds must be sorted by phase
+fn plateau(t: f64, holds: &[(f64, f64); 2]) -> f64 {
+    let speed = 1.0 - holds[0].1 - holds[1].1;
+    let mut held = 0.0;
+    for (at, length) in holds {
+        let start = at * speed + held;
+        if t < start {
+            break;
+        }
+        if t < start + length {
+            return *at;
+        }
+        held += length;
+    }
+    ((t - held) / speed).clamp(0.0, 1.0 - f64::EPSILON)
+}
+
+// the nearest of `steps` evenly spaced levels across -1.0..1.0
+fn quantize_steps(value: f64, steps: u16) -> f64 {
+    let intervals = steps.max(2) as f64 - 1.0;
+    ((0.5 * (value + 1.0) * intervals).round() / intervals * 2.0 - 1.0).clamp(-1.0, 1.0)
+}
+
+// `value` -1.0..1.0
+fn shape_output(value: f64, shaper: Shaper, drive: f64) -> f64 {
+    let driven = value * drive;
+    match shaper {
+        Shaper::Tanh => driven.tanh() / drive.tanh(),
+        Shaper::Fold => 1.0 - ((driven + 1.0).rem_euclid(4.0) - 2.0).abs(),
+        Shaper::Clip => driven.clamp(-1.0, 1.0),
+    }
+}
+
+// `position` 0.0..1.0 along `min..max`; the exponential and logarithmic mappings fall back to a
+// straight line when the ends straddle or touch zero
+fn map_range(position: f64, min: f64, max: f64, curve: RangeCurve) -> f64 {
+    let geometric = min * max > 0.0;
+    match curve {
+        RangeCurve::Exponential if geometric => min * (max / min).powf(position),
+        RangeCurve::Logarithmic if geometric => min + max - max * (min / max).powf(position),
+        RangeCurve::Decibels => 10f64.powf((min + (max - min) * position) / 20.0),
+        _ => min + (max - min) * position,
+    }
+}
+
+// phase warp that delays the second half of the cycle: it starts at 0.5 + amount / 2
+// and is squeezed to fit, `amount` 0.0..1.0
+fn swing(phase: f64, amount: f64) -> f64 {
+    knee(phase, 0.5 + 0.5 * amount.clamp(0.0, 0.99))
+}
+
+// Casio CZ style: the first half of the cycle is squeezed into less and less time, turning a
+// sine ever brighter, towards a saw
+fn phase_distortion(phase: f64, amount: f64) -> f64 {
+    knee(phase, 0.5 - 0.5 * amount.clamp(0.0, 0.99))
+}
+
+// straight lines from 0.0 up to half way at `at`, then on to the end of the cycle
+fn knee(phase: f64, at: f64) -> f64 {
+    if phase < at {
+        0.5 * phase / at
+    } else {
+        0.5 + 0.5 * (phase - at) / (1.0 - at)
+    }
+}
+
+// integer hash mapped to a gradient in -1.0..1.0
+fn gradient(seed: u64, i: i64) -> f64 {
+    let mut h = seed ^ (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^= h >> 31;
+    (h >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+}
+
+fn perlin(seed: u64, x: f64, octaves: u32, persistence: f64) -> f64 {
+    let mut sum = 0.0;
+    let mut norm = 0.0;
+    let mut amp = 1.0;
+    let mut freq = 1.0;
+    for octave in 0..octaves.max(1) {
+        // offset each octave so their zero-valued lattice points don't line up
+        let x = x * freq + octave as f64 * 0.618_034;
+        let i = x.floor();
+        let f = x - i;
+        let fade = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+        let octave_seed = seed.wrapping_add(octave as u64);
+        let g0 = gradient(octave_seed, i as i64) * f;
+        let g1 = gradient(octave_seed, i as i64 + 1) * (f - 1.0);
+        // a 1D gradient noise peaks at +-0.5, so double it to fill -1.0..1.0
+        sum += amp * 2.0 * (g0 + (g1 - g0) * fade);
+        norm += amp;
+        amp *= persistence;
+        freq *= 2.0;
+    }
+    sum / norm
+}
+
+fn interpolate(points: &[f64; 4], t: f64, interpolation: Interpolation) -> f64 {
+    let [p0, p1, p2, p3] = *points;
+    match interpolation {
+        Interpolation::None => p1,
+        Interpolation::Linear => p1 + (p2 - p1) * t,
+        Interpolation::Cosine => {
+            let t = 0.5 * (1.0 - (0.5 * TAU * t).cos());
+            p1 + (p2 - p1) * t
+        },
+        Interpolation::Cubic => {
+            // Catmull-Rom through p1..p2, using p0 and p3 as tangent neighbours
+            let a = -0.5 * p0 + 1.5 * p1 - 1.5 * p2 + 0.5 * p3;
+            let b = p0 - 2.5 * p1 + 2.0 * p2 - 0.5 * p3;
+            let c = -0.5 * p0 + 0.5 * p2;
+            (((a * t + b) * t + c) * t + p1).clamp(-1.0, 1.0)
+        },
+    }
+}
+
+// true when the phase has moved into a new one of `steps` equal subdivisions of the cycle,
+// wrapping past 1.0 when playing forwards or past 0.0 when playing backwards
+fn crossed_step(last_phase: f64, phase: f64, steps: u32, forward: bool) -> bool {
+    let n = steps.max(1) as f64;
+    let wrapped = if forward { phase < last_phase } else { phase > last_phase };
+    wrapped || (phase * n).floor() != (last_phase * n).floor()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    None,
+    Linear,
+    Cosine,
+    Cubic,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StairBase {
+    Triangle,
+    SawUp,
+    SawDn,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BumpShape {
+    RaisedCosine,
+    Gaussian,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    InQuad,
+    OutQuad,
+    InOutQuad,
+    InCubic,
+    OutCubic,
+    InOutCubic,
+    InOutSine,
+    InExpo,
+    OutExpo,
+    OutElastic,
+    OutBounce,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Breakpoint {
+    pub time: f64, // position in the cycle, 0.0 <= t < 1.0
+    pub value: f64,
+    pub curve: f64, // bend of the segment leaving this point, -1.0 <= c <= 1.0
+}
+
+impl Breakpoint {
+    pub fn new(time: f64, value: f64, curve: f64) -> Self {
+        Breakpoint { time, value, curve }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriggerMode {
+    Free, // trigger() is ignored
+    Retrigger, // trigger() restarts the cycle
+    OneShot, // trigger() restarts the cycle, which then plays once and stops
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyncMode {
+    Hard, // a sync edge restarts the cycle
+    Soft, // a sync edge reverses the direction of travel through the cycle
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecayCurve {
+    Linear, // reaches nothing after the decay time
+    Exponential, // falls to 1/e of the depth every decay time
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Companion {
+    Gate, // 1.0 through the first half of each cycle, 0.0 through the second
+    Sync, // 1.0 for the single sample starting each cycle
+}
+
+// reshapes time within the cycle, for accelerating or decelerating motion from any waveform
+#[derive(Clone, Copy, Debug)]
+pub enum PhaseWarp {
+    Exponential(f64), // -1.0..1.0, positive starts slow and speeds up, negative the reverse
+    Sigmoid(f64), // 0.0..1.0, lingers at both ends of the cycle and rushes through the middle
+    Custom(fn(f64) -> f64), // maps 0.0..1.0 onto itself
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SweepMode {
+    Once, // stop at the end value
+    PingPong, // turn around at either end
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputMode {
+    Bipolar, // -gain..gain
+    Unipolar, // 0.0..gain
+    InvertedUnipolar, // gain..0.0, at the top where the waveform is at the bottom
+}
+
+// how the output moves between the ends of its range
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RangeCurve {
+    Linear,
+    Exponential, // equal ratios for equal steps, e.g. octaves of a filter cutoff; needs both ends the same sign
+    Logarithmic, // the mirror image of exponential, moving fastest near the bottom
+    Decibels, // ends in dB, swept evenly, giving the amplitude factor, e.g. -24.0..0.0 for tremolo
+}
+
+// saturation applied to the output after the gain, driven harder the higher the drive
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shaper {
+    Tanh, // soft saturation, scaled so full scale still reaches full scale
+    Fold, // reflects back off the top and bottom of the range, again and again as the drive rises
+    Clip, // flattens off at the top and bottom
+}
+
+// spreads the rounding error of the output steps so their average follows the waveform
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dither {
+    Tpdf, // triangular noise one step either side
+    ErrorFeedback, // each sample's rounding error is taken off the next one
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SkipMode {
+    Hold, // keep the last output of the previous cycle
+    Center, // sit at the middle of the range
+}
+
+// transitions[i][j] is the relative weight of moving from waveform i to waveform j at a cycle end
+pub struct WaveformChain {
+    waveforms: Vec<Waveform>,
+    transitions: Vec<Vec<f64>>,
+}
+
+impl WaveformChain {
+    pub fn new(waveforms: Vec<Waveform>, transitions: Vec<Vec<f64>>) -> Self {
+        WaveformChain { waveforms, transitions }
+    }
+}
+
+// a point already on the attractor, so there is no slow start-up spiral
+const LORENZ_START: [f64; 3] = [1.169, -4.399, 28.253];
+
+const LOGISTIC_START: f64 = 0.4;
+
+const SPLINE_TABLE_SIZE: usize = 1024;
+
 pub enum Waveform {
     Sine,
     Triangle,
     SawUp,
     SawDn,
     Pulse(f64),
+    SampleHold(u32), // number of random steps per cycle
+    RandomSmooth(Interpolation), // one random target per cycle
+    Perlin { octaves: u32, persistence: f64 }, // one lattice point per cycle
+    PinkNoise, // one noise step per cycle, linearly interpolated
+    BrownNoise,
+    Drunk { step: f64, min: f64, max: f64 }, // one bounded random step per cycle
+    ExpSaw(f64), // time constant as a fraction of the cycle
+    SineHalf,
+    SineFullRect,
+    SineQuarter,
+    PowerSine(f64), // shape exponent
+    Trapezoid { rise: f64, fall: f64 },
+    Stairs { steps: u32, base: StairBase },
+    StepSeq { levels: Vec<f64>, glide: f64 },
+    Euclid { pulses: u32, steps: u32, rotation: u32 },
+    Lorenz, // x of the Lorenz attractor, freq sets integration speed
+    Logistic { r: f64 }, // one step of the logistic map per cycle
+    Harmonics(Vec<(f64, f64)>),
+    Wavetable { table: Vec<f64>, interpolation: Interpolation },
+    WavetableBank { tables: Vec<Vec<f64>>, interpolation: Interpolation }, // see set_morph
+    Segments(Vec<Breakpoint>),
+    Spline(Vec<(f64, f64)>), // (time, value) control points
+    Custom(Box<dyn FnMut(f64) -> f64 + Send>), // phase in, -1.0..1.0 out
+    Bump { width: f64, shape: BumpShape },
+    BouncingBall { bounces: u32, damping: f64 },
+    SkewTriangle(f64), // symmetry, 0.0 <= s <= 1.0
+    CurvedSaw { bend: f64, ramp_up: bool }, // bend as in Breakpoint::curve
+    Pattern(u32, u8), // gate bits and pattern length
+    Parabolic,
+    Mix(Box<Waveform>, Box<Waveform>, f64), // crossfade from the first to the second, 0.0 <= x <= 1.0
+    Chirp { start: f64, end: f64 }, // frequency multiples of the LFO rate
+    Ease(Easing),
+    RC { up: f64, down: f64 }, // charge and discharge time constants as fractions of the cycle
+    Choose(Vec<(Waveform, f64)>), // a new weighted pick every cycle
+    Chain(WaveformChain), // starts at the first waveform
+    Wander { center: f64, stiffness: f64, noise: f64 },
+    Gaussian { sigma: f64, interpolation: Interpolation }, // one normal draw per cycle
+}
+
+// an external signal driving a parameter, read once per sample and scaled by `depth`
+struct Modulation {
+    input: Box<dyn Signal<Frame = f64> + Send>,
+    depth: f64,
+}
+
+impl Modulation {
+    fn new<S: Signal<Frame = f64> + Send + 'static>(input: S, depth: f64) -> Self {
+        Modulation { input: Box::new(input), depth }
+    }
+
+    fn next(&mut self) -> f64 {
+        self.depth * self.input.next()
+    }
+}
+
+// pulse width moving from `start` to `end` over `secs`
+struct DutySweep {
+    start: f64,
+    end: f64,
+    secs: f64,
+    mode: SweepMode,
+    elapsed: f64, // seconds since the sweep (re)started
+}
+
+impl DutySweep {
+    fn duty(&self) -> f64 {
+        let t = if self.secs <= 0.0 {
+            1.0
+        } else {
+            let t = self.elapsed / self.secs;
+            match self.mode {
+                SweepMode::Once => t.min(1.0),
+                SweepMode::PingPong => 1.0 - (t.rem_euclid(2.0) - 1.0).abs(),
+            }
+        };
+        self.start + (self.end - self.start) * t
+    }
+}
+
+// schmitt trigger on the output: high above `level + hysteresis / 2`, low below `level - hysteresis / 2`
+struct Threshold {
+    level: f64,
+    hysteresis: f64,
+    edge: Edge,
+    high: Option<bool>, // unknown until the first sample
+    fired: bool,
+}
+
+impl Threshold {
+    fn update(&mut self, value: f64) {
+        let half = 0.5 * self.hysteresis;
+        let high = match self.high {
+            Some(false) => value > self.level + half,
+            Some(true) => value >= self.level - half,
+            None => value > self.level,
+        };
+        self.fired = match (self.high, self.edge) {
+            (None, _) => false,
+            (Some(was), Edge::Rising) => high && !was,
+            (Some(was), Edge::Falling) => !high && was,
+            (Some(was), Edge::Both) => high != was,
+        };
+        self.high = Some(high);
+    }
+}
+
+// slow random imperfections of a hardware LFO, `amount` 0.0..1.0 scaling all of them
+struct Analog {
+    amount: f64,
+    rng: Noise,
+    wander: [f64; 2], // frequency and amplitude deviation, -1.0..1.0
+    targets: [f64; 2],
+    countdown: f64, // samples until new wander targets are drawn
+    jitter: f64, // phase jitter still to be applied this cycle
+    offset: f64, // accumulated phase offset in cycles
+}
+
+impl Analog {
+    const FREQ_DRIFT: f64 = 0.02;
+    const AMP_WOBBLE: f64 = 0.05;
+    const PHASE_JITTER: f64 = 0.01;
+    const WANDER_SECS: f64 = 2.0;
+
+    fn new(amount: f64, seed: u64) -> Self {
+        Analog {
+            amount,
+            rng: signal::noise(seed.wrapping_add(1)),
+            wander: [0.0; 2],
+            targets: [0.0; 2],
+            countdown: 0.0,
+            jitter: 0.0,
+            offset: 0.0,
+        }
+    }
+
+    fn amplitude(&self) -> f64 {
+        1.0 + self.amount * Self::AMP_WOBBLE * self.wander[1]
+    }
+
+    // advance one sample; the offset never moves back by more than half a phase increment,
+    // so the phase stays monotonic and cycle boundaries are still seen exactly once
+    fn tick(&mut self, freq: f64, sample_rate: f64, wrapped: bool) {
+        if self.countdown <= 0.0 {
+            self.targets = [self.rng.next(), self.rng.next()];
+            self.countdown = Self::WANDER_SECS * sample_rate;
+        }
+        self.countdown -= 1.0;
+        let follow = 1.0 / (Self::WANDER_SECS * sample_rate);
+        for (w, t) in self.wander.iter_mut().zip(self.targets) {
+            *w += (t - *w) * follow;
+        }
+        if wrapped {
+            self.jitter = self.amount * Self::PHASE_JITTER * self.rng.next();
+        }
+        let increment = freq.abs() / sample_rate;
+        let nudge = self.jitter.clamp(-0.5 * increment, 0.5 * increment);
+        self.jitter -= nudge;
+        let drift = increment * self.amount * Self::FREQ_DRIFT * self.wander[0];
+        self.offset = (self.offset + drift + nudge).rem_euclid(1.0);
+    }
+}
+
+// running state of a waveform; each child of a composite waveform keeps its own
+#[derive(Clone, Copy)]
+struct ShapeState {
+    random: [f64; 4], // most recently drawn values, newest last
+    pink: [f64; 8], // Voss-McCartney rows
+    pink_counter: u32,
+    brown: f64,
+    walk: f64,
+    lorenz: [f64; 3],
+    logistic: f64,
+    rc: f64,
+    choice: Option<usize>, // option picked for this cycle by Waveform::Choose
+}
+
+impl ShapeState {
+    fn new(random: [f64; 4]) -> Self {
+        ShapeState {
+            random,
+            pink: [0.0; 8],
+            pink_counter: 0,
+            brown: 0.0,
+            walk: 0.0,
+            lorenz: LORENZ_START,
+            logistic: LOGISTIC_START,
+            rc: 0.0,
+            choice: None,
+        }
+    }
+}
+
+// number of waveforms in the tree rooted at `waveform`, itself included
+fn tree_size(waveform: &Waveform) -> usize {
+    1 + match waveform {
+        Waveform::Mix(a, b, _) => tree_size(a) + tree_size(b),
+        Waveform::Choose(options) => options.iter().map(|(w, _)| tree_size(w)).sum(),
+        Waveform::Chain(chain) => chain.waveforms.iter().map(tree_size).sum(),
+        _ => 0,
+    }
+}
+
+// fills in the table of every Spline in the tree, `node` being the position of `waveform`
+fn render_splines(waveform: &Waveform, node: usize, tables: &mut [Vec<f64>]) {
+    let mut children = |waveforms: &mut dyn Iterator<Item = &Waveform>| {
+        let mut child = node + 1;
+        for waveform in waveforms {
+            render_splines(waveform, child, tables);
+            child += tree_size(waveform);
+        }
+    };
+    match waveform {
+        Waveform::Spline(points) => {
+            tables[node] = (0..SPLINE_TABLE_SIZE).map(|i| {
+                spline(i as f64 / SPLINE_TABLE_SIZE as f64, points)
+            }).collect();
+        },
+        Waveform::Mix(a, b, _) => children(&mut [&**a, &**b].into_iter()),
+        Waveform::Choose(options) => children(&mut options.iter().map(|(w, _)| w)),
+        Waveform::Chain(chain) => children(&mut chain.waveforms.iter()),
+        _ => {},
+    }
 }
 
 pub struct LFO {
     waveform: Waveform,
     freq: f64,
+    tempo: Option<f64>, // bpm the rate follows, if synced
+    tempo_freq: f64, // the synced rate, which freq glides towards
+    tempo_follow: f64, // time constant of the glide in seconds, 0.0 to jump
+    division: Division,
     theta: f64,
     gain: f64, // -1.0 <= g <= 1.0
-    time_step: f64,
+    output_mode: OutputMode,
+    shaper: Option<(Shaper, f64)>,
+    dead_zone: f64,
+    invert: bool,
+    steps: Option<u16>,
+    dither: Option<Dither>,
+    dither_rng: Noise,
+    dither_offset: f64, // added before the steps this sample
+    dither_error: f64, // rounding error of the last sample, for error feedback
+    output_range: Option<(f64, f64)>,
+    range_curve: RangeCurve,
+    offset: f64,
+    clamp: Option<(f64, f64)>,
+    scale: Option<Scale>,
+    slew: Option<(f64, f64)>, // fastest rise and fall in output units per second
+    hysteresis: f64,
+    held: Option<f64>, // output the hysteresis is holding on to
+    smoothing: f64, // time constant of the output lowpass in seconds, 0.0 for none
+    last_output: Option<f64>,
+    morph: f64, // 0.0 <= m <= 1.0
+    phase: f64, // accumulated phase in cycles, 0.0 <= p < 1.0
+    direction: f64, // 1.0 forwards, -1.0 while soft sync has reversed playback
+    last_step: f64, // signed increment that moved the accumulator onto its current phase
+    forward: bool, // the current sample was reached moving forwards through the cycle
     sample_rate: f64,
+    seed: u64,
+    rng: Noise,
+    state: ShapeState, // of the waveform being shaped, swapped in from `nested` for children
+    nested: Vec<ShapeState>, // children of Mix, Choose and Chain in tree order
+    saved: Vec<ShapeState>, // copy of `nested` while running undisturbed
+    spline_tables: Vec<Vec<f64>>, // Spline waveforms pre-rendered, by tree position
+    analog: Option<Analog>,
+    random_start: Option<Noise>, // draws the starting phase on reset when set
+    phase_mod: Option<Modulation>,
+    pm: f64, // phase modulation of the current sample in cycles
+    freq_mod: Option<Modulation>,
+    duty_mod: Option<Modulation>,
+    dm: f64, // duty ratio modulation of the current sample
+    duty_sweep: Option<DutySweep>,
+    trigger_mode: TriggerMode,
+    finished: bool, // a one-shot cycle or the loop count has played out
+    loop_count: Option<u32>,
+    loop_points: Option<(f64, f64)>, // start and end phases of a sub-cycle loop
+    loops: u32, // cycles completed since the last reset
+    frozen: bool,
+    transport_stopped: bool, // held by set_transport(), separately from freeze()
+    delay: f64, // seconds of idle output after each reset
+    idle: f64,
+    delay_left: f64, // samples of the current delay still to wait
+    waiting: bool, // the current sample was spent in the delay
+    fade_in: f64, // seconds for the depth to ramp up after each reset
+    decay: f64, // seconds, 0.0 when the depth doesn't decay
+    decay_curve: DecayCurve,
+    elapsed: f64, // seconds played since the last reset, not counting the delay
+    skip_probability: f64,
+    skip_mode: SkipMode,
+    skipping: bool, // the current cycle is being skipped
+    last_value: f64,
+    ratchet: u32, // repeats squeezed into the current cycle
+    swing: f64,
+    distortion: f64,
+    time_warp: Option<PhaseWarp>,
+    plateau: (f64, f64), // fractions of the cycle held at the waveform's maximum and minimum
+    extremes: (f64, f64), // phases of the maximum and minimum, measured when the plateau is set
+    ratchet_pending: u32,
+    cycle_phase: f64, // phase of the previous sample before any warping
+    last_phase: f64, // phase the waveform saw on the previous sample
+    cycles: u64, // completed cycles since construction
+    wrapped: bool, // the last sample started a new cycle
+    restarted: bool, // reset since the last sample, so the next one starts a cycle too
+    started: bool, // the last sample started a cycle by wrapping or restarting
+    threshold: Option<Threshold>,
+    ensemble: Vec<(f64, f64)>, // per voice: phase offset from the main phase, rate ratio
+    reset_in: Option<usize>, // samples until a scheduled reset
 }
 
 impl LFO {
     pub fn new(waveform: Waveform, freq: f64, sample_rate: f64) -> Self {
-        LFO {
-            waveform: waveform,
-            freq: freq,
+        let mut rng = signal::noise(0);
+        let random = [rng.next(), rng.next(), rng.next(), rng.next()];
+        let mut lfo = LFO {
+            waveform,
+            freq,
+            tempo: None,
+            tempo_freq: freq,
+            tempo_follow: 0.0,
+            division: NoteValue::Quarter.into(),
             theta: 0.0,
             gain: 1.0,
-            time_step: 0.0,
-            sample_rate: sample_rate,
-        }
+            output_mode: OutputMode::Unipolar,
+            shaper: None,
+            dead_zone: 0.0,
+            invert: false,
+            steps: None,
+            dither: None,
+            dither_rng: signal::noise(3),
+            dither_offset: 0.0,
+            dither_error: 0.0,
+            output_range: None,
+            range_curve: RangeCurve::Linear,
+            offset: 0.0,
+            clamp: None,
+            scale: None,
+            slew: None,
+            hysteresis: 0.0,
+            held: None,
+            smoothing: 0.0,
+            last_output: None,
+            morph: 0.0,
+            phase: 0.0,
+            direction: 1.0,
+            last_step: 0.0,
+            forward: true,
+            sample_rate,
+            seed: 0,
+            rng,
+            state: ShapeState::new(random),
+            nested: Vec::new(),
+            saved: Vec::new(),
+            spline_tables: Vec::new(),
+            analog: None,
+            random_start: None,
+            phase_mod: None,
+            pm: 0.0,
+            freq_mod: None,
+            duty_mod: None,
+            dm: 0.0,
+            duty_sweep: None,
+            trigger_mode: TriggerMode::Free,
+            finished: false,
+            loop_count: None,
+            loop_points: None,
+            loops: 0,
+            frozen: false,
+            transport_stopped: false,
+            delay: 0.0,
+            idle: 0.0,
+            delay_left: 0.0,
+            waiting: false,
+            fade_in: 0.0,
+            decay: 0.0,
+            decay_curve: DecayCurve::Exponential,
+            elapsed: 0.0,
+            skip_probability: 0.0,
+            skip_mode: SkipMode::Hold,
+            skipping: false,
+            last_value: 0.0,
+            ratchet: 1,
+            swing: 0.0,
+            distortion: 0.0,
+            time_warp: None,
+            plateau: (0.0, 0.0),
+            extremes: (0.0, 0.0),
+            ratchet_pending: 1,
+            cycle_phase: 0.0,
+            last_phase: 0.0,
+            cycles: 0,
+            wrapped: false,
+            restarted: false,
+            started: false,
+            threshold: None,
+            ensemble: Vec::new(),
+            reset_in: None,
+        };
+        lfo.prepare_waveform();
+        lfo
     }
 
     pub fn set_waveform(&mut self, waveform: Waveform) {
         self.waveform = waveform;
+        self.state.choice = None;
+        self.prepare_waveform();
+        if self.plateau != (0.0, 0.0) {
+            self.extremes = self.find_extremes();
+        }
     }
 
+    // a negative rate plays the waveform backwards; this stops following any tempo
     pub fn set_freq(&mut self, freq: f64) {
+        self.tempo = None;
         self.freq = freq;
     }
 
-    pub fn set_theta(&mut self, theta: f64) {
-        self.theta = theta;
+    // seconds per cycle, the same as set_freq(1.0 / secs); anything but a finite positive
+    // period is ignored
+    pub fn set_period_secs(&mut self, secs: f64) {
+        if secs.is_finite() && secs > 0.0 {
+            self.set_freq(1.0 / secs);
+        }
     }
 
-    pub fn set_gain(&mut self, gain: f64) {
-        self.gain = gain;
+    pub fn set_period_ms(&mut self, ms: f64) {
+        self.set_period_secs(ms / 1000.0);
     }
 
-    pub fn reset(&mut self) {
-        self.time_step = 0.0;
+    // the length of a cycle whichever way it plays; f64::INFINITY while stopped at 0Hz
+    pub fn period_secs(&self) -> f64 {
+        1.0 / self.freq.abs()
     }
 
-    fn generate(&mut self) -> f64 {
-        let phase = phase(self.freq, self.time_step / self.sample_rate, self.theta);
-        self.time_step = ((self.time_step + 1.0) as usize % self.sample_rate as usize) as f64;
-        match self.waveform {
-            Waveform::Sine => {
-                sine(phase)
-            },
-            Waveform::Triangle => {
-                triangle(phase)
-            },
-            Waveform::SawUp => {
-                saw(phase, true)
-            },
-            Waveform::SawDn => {
-                saw(phase, false)
-            }
-            Waveform::Pulse(duty_ratio) => {
-                pulse(phase, duty_ratio)
-            },
-        }
+    pub fn period_ms(&self) -> f64 {
+        self.period_secs() * 1000.0
     }
-}
 
-impl Signal for LFO {
-    type Frame = f64;
+    // the rate follows `bpm`, one cycle per rate division
+    pub fn set_tempo(&mut self, bpm: f64) {
+        let glide = self.tempo.is_some() && self.tempo_follow > 0.0;
+        self.tempo = Some(bpm);
+        if glide {
+            self.tempo_freq = self.division.freq(bpm);
+        } else {
+            self.sync_rate();
+        }
+    }
 
-    fn next(&mut self) -> Self::Frame {
-        let amp = 0.5 * self.gain;
-        amp * (self.generate() + 1.0)
+    // glide to the rate of a new tempo over roughly `secs` instead of jumping, for hosts
+    // that ramp the tempo or clocks that jitter; changing the rate division still jumps
+    pub fn set_tempo_follow(&mut self, secs: f64) {
+        self.tempo_follow = secs.max(0.0);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use plotters::prelude::*;
+    // takes effect once a tempo is set
+    pub fn set_rate_division(&mut self, division: impl Into<Division>) {
+        self.division = division.into();
+        self.sync_rate();
+    }
 
-    fn create_chart(lfo: &mut LFO, t_sec: f64, filename: &str, cap: &str) {
-        let data_len: usize = (lfo.sample_rate * t_sec) as usize;
-        let lfo_vec: Vec<f64> = (0..=data_len).map(|_i| {
-            lfo.next()
-        }).collect();
+    // for plugin hosts: call once per block with the song position of its first sample, and the
+    // phase is worked out from the position at the synced rate, so every pass over the song
+    // modulates the same way; a stopped transport holds the output
+    pub fn set_transport(&mut self, position_samples: f64, tempo: f64, playing: bool) {
+        self.set_transport_stopped(!playing);
+        if !playing {
+            return;
+        }
+        self.set_tempo(tempo);
+        let beat = position_samples / self.sample_rate * tempo / 60.0;
+        self.nudge_phase(self.beat_error(beat));
+    }
+
+    fn sync_rate(&mut self) {
+        if let Some(bpm) = self.tempo {
+            self.tempo_freq = self.division.freq(bpm);
+            self.freq = self.tempo_freq;
+        }
+    }
+
+    // the accumulated phase is independent of the rate, so only sample-counted state needs rescaling
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        if let Some(analog) = &mut self.analog {
+            analog.countdown *= sample_rate / self.sample_rate;
+        }
+        self.delay_left *= sample_rate / self.sample_rate;
+        self.sample_rate = sample_rate;
+    }
+
+    // position in the cycle of the next sample, before the theta offset
+    pub fn phase(&self) -> f64 {
+        self.phase
+    }
+
+    // jumping is not counted as completing a cycle
+    pub fn set_phase(&mut self, phase: f64) {
+        self.phase = phase.rem_euclid(1.0);
+        self.cycle_phase = self.offset_phase();
+    }
+
+    // phase offset in cycles, wrapped into 0.0..1.0
+    pub fn set_theta(&mut self, theta: f64) {
+        self.theta = theta.rem_euclid(1.0);
+    }
+
+    pub fn set_phase_cycles(&mut self, cycles: f64) {
+        self.set_theta(cycles);
+    }
+
+    pub fn set_phase_degrees(&mut self, degrees: f64) {
+        self.set_theta(degrees / 360.0);
+    }
+
+    pub fn set_gain(&mut self, gain: f64) {
+        self.gain = gain;
+    }
+
+    pub fn set_output_mode(&mut self, mode: OutputMode) {
+        self.output_mode = mode;
+    }
+
+    // `drive` from 1.0 up; with less gain there is less to push into the shaper
+    pub fn set_shaper(&mut self, shaper: Shaper, drive: f64) {
+        self.shaper = Some((shaper, drive.max(1.0)));
+    }
+
+    pub fn clear_shaper(&mut self) {
+        self.shaper = None;
+    }
+
+    // anything within `width` of the middle of the range, in -1.0..1.0 full scale after the
+    // gain and shaper, sits exactly at the middle
+    pub fn set_dead_zone(&mut self, width: f64) {
+        self.dead_zone = width.abs();
+    }
+
+    // the output only moves once it would move by more than `amount`, in output units, from
+    // where it last settled, so small wiggles don't make whatever it drives chatter
+    pub fn set_hysteresis(&mut self, amount: f64) {
+        self.hysteresis = amount.abs();
+        self.held = None;
+    }
+
+    // flips the output over around the middle of its range, whatever the mode and gain
+    pub fn set_invert(&mut self, invert: bool) {
+        self.invert = invert;
+    }
+
+    // snaps the output to `steps` evenly spaced levels from the bottom of its range to the top,
+    // at least two
+    pub fn set_steps(&mut self, steps: Option<u16>) {
+        self.steps = steps;
+    }
+
+    // only has an effect while there are steps
+    pub fn set_dither(&mut self, dither: Option<Dither>) {
+        self.dither = dither;
+        self.dither_error = 0.0;
+    }
+
+    // maps the output mode's full scale, -1.0..1.0 bipolar or 0.0..1.0 otherwise, onto
+    // `min..max` in the target parameter's own units, e.g. 200.0..8000.0 for a cutoff in Hz
+    pub fn set_output_range(&mut self, min: f64, max: f64) {
+        self.output_range = Some((min, max));
+    }
+
+    pub fn clear_output_range(&mut self) {
+        self.output_range = None;
+    }
+
+    pub fn set_range_curve(&mut self, curve: RangeCurve) {
+        self.range_curve = curve;
+    }
+
+    // added last, in the output's own units, so with bipolar output the gain is the modulation
+    // amount either side of the offset
+    pub fn set_offset(&mut self, offset: f64) {
+        self.offset = offset;
+    }
+
+    // keeps the offset output inside `min..max`
+    pub fn set_clamp(&mut self, range: Option<(f64, f64)>) {
+        self.clamp = range;
+    }
+
+    // treats the output as semitones and snaps it to the nearest note of `scale`, so set the
+    // range to the interval to cover first, e.g. set_output_range(0.0, 24.0) for two octaves
+    pub fn set_scale(&mut self, scale: Option<Scale>) {
+        self.scale = scale;
+    }
+
+    // limits how fast the output may move, in output units per second up and down; pass
+    // f64::INFINITY to leave a direction free
+    pub fn set_slew(&mut self, rise: f64, fall: f64) {
+        self.slew = Some((rise.abs(), fall.abs()));
+    }
+
+    pub fn clear_slew(&mut self) {
+        self.slew = None;
+    }
+
+    // a one-pole lowpass on the output, closing 63% of the way to a new value every
+    // `time_constant` seconds; 0.0 turns it off
+    pub fn set_smoothing(&mut self, time_constant: f64) {
+        self.smoothing = time_constant.max(0.0);
+    }
+
+    pub fn set_morph(&mut self, morph: f64) {
+        self.morph = morph.clamp(0.0, 1.0);
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = signal::noise(seed);
+        // every stream has its own offset from the seed: analog drift 1, random start 2, dither 3
+        self.dither_rng = signal::noise(seed.wrapping_add(3));
+        for _ in 0..self.state.random.len() {
+            self.draw();
+        }
+        self.state.pink = [0.0; 8];
+        self.state.pink_counter = 0;
+        self.state.brown = 0.0;
+        self.state.walk = 0.0;
+        self.state.lorenz = LORENZ_START;
+        self.state.logistic = LOGISTIC_START;
+        self.nest_states();
+        if let Some(analog) = &self.analog {
+            self.analog = Some(Analog::new(analog.amount, seed));
+        }
+        if self.random_start.is_some() {
+            self.random_start = Some(signal::noise(seed.wrapping_add(2)));
+        }
+    }
+
+    // reset() and trigger() start at a random phase, so voices sharing a rate don't pump together
+    pub fn set_random_start(&mut self, enabled: bool) {
+        self.random_start = enabled.then(|| signal::noise(self.seed.wrapping_add(2)));
+    }
+
+    // 0.0 turns the analog imperfections off
+    pub fn set_analog(&mut self, amount: f64) {
+        let amount = amount.clamp(0.0, 1.0);
+        match &mut self.analog {
+            _ if amount == 0.0 => self.analog = None,
+            Some(analog) => analog.amount = amount,
+            None => self.analog = Some(Analog::new(amount, self.seed)),
+        }
+    }
+
+    // `depth` cycles of phase shift per unit of input, added just before the waveform lookup
+    pub fn set_phase_mod<S: Signal<Frame = f64> + Send + 'static>(&mut self, input: S, depth: f64) {
+        self.phase_mod = Some(Modulation::new(input, depth));
+    }
+
+    pub fn clear_phase_mod(&mut self) {
+        self.phase_mod = None;
+        self.pm = 0.0;
+    }
+
+    // `depth` Hz of frequency deviation per unit of input, so another LFO can sweep the rate
+    pub fn set_freq_mod<S: Signal<Frame = f64> + Send + 'static>(&mut self, input: S, depth: f64) {
+        self.freq_mod = Some(Modulation::new(input, depth));
+    }
+
+    pub fn clear_freq_mod(&mut self) {
+        self.freq_mod = None;
+    }
+
+    // pulse width modulation: `depth` of duty ratio per unit of input, clamped to 0.0..1.0
+    pub fn set_duty_mod<S: Signal<Frame = f64> + Send + 'static>(&mut self, input: S, depth: f64) {
+        self.duty_mod = Some(Modulation::new(input, depth));
+    }
+
+    pub fn clear_duty_mod(&mut self) {
+        self.duty_mod = None;
+        self.dm = 0.0;
+    }
+
+    // the pulse width glides from `start` to `end` over `secs`, in place of the Pulse duty ratio,
+    // restarting on reset; any duty modulation is added on top
+    pub fn set_duty_sweep(&mut self, start: f64, end: f64, secs: f64, mode: SweepMode) {
+        self.duty_sweep = Some(DutySweep { start, end, secs: secs.max(0.0), mode, elapsed: 0.0 });
+    }
+
+    pub fn clear_duty_sweep(&mut self) {
+        self.duty_sweep = None;
+    }
+
+    // each new cycle is skipped with `probability`, outputting `mode` instead
+    pub fn set_cycle_skip(&mut self, probability: f64, mode: SkipMode) {
+        self.skip_probability = probability.clamp(0.0, 1.0);
+        self.skip_mode = mode;
+        if self.skip_probability == 0.0 {
+            self.skipping = false;
+        }
+    }
+
+    // the next cycle plays the waveform `repeats` times over, then normal speed resumes
+    pub fn ratchet(&mut self, repeats: u32) {
+        self.ratchet_pending = repeats.max(1);
+    }
+
+    pub fn set_trigger_mode(&mut self, mode: TriggerMode) {
+        self.trigger_mode = mode;
+        if mode != TriggerMode::OneShot {
+            self.finished = false;
+        }
+    }
+
+    // stand still for `top` of the cycle at the waveform's maximum and `bottom` at its minimum,
+    // the rest of the cycle speeding up to make room; the extremes are found once, here and in
+    // set_waveform(), so shapes that change every cycle hold wherever they peaked at the time
+    pub fn set_plateau(&mut self, top: f64, bottom: f64) {
+        let top = top.clamp(0.0, 0.95);
+        let bottom = bottom.clamp(0.0, 0.95 - top);
+        self.plateau = (top, bottom);
+        self.extremes = self.find_extremes();
+    }
+
+    // None plays through the cycle at an even pace
+    pub fn set_phase_warp(&mut self, warp: Option<PhaseWarp>) {
+        self.time_warp = warp;
+    }
+
+    // delays every other half-cycle by `amount` of a half-cycle, 0.0..1.0; with ratcheting it
+    // applies to each repeat, so subdivisions swing
+    pub fn set_swing(&mut self, amount: f64) {
+        self.swing = amount.clamp(0.0, 1.0);
+    }
+
+    // 0.0..1.0, from the plain waveform to its first half rushed through in almost no time
+    pub fn set_phase_distortion(&mut self, amount: f64) {
+        self.distortion = amount.clamp(0.0, 1.0);
+    }
+
+    // Some(n) plays n cycles after each reset or trigger, at least one, then holds the final value
+    pub fn set_loop_count(&mut self, count: Option<u32>) {
+        self.loop_count = count;
+    }
+
+    // once the phase reaches `end` it jumps back to `start`, so everything before `start` plays
+    // once per reset as an intro and start..end repeats; each jump counts as a new cycle
+    pub fn set_loop_points(&mut self, points: Option<(f64, f64)>) {
+        self.loop_points = points
+            .map(|(start, end)| (start.clamp(0.0, 1.0), end.clamp(0.0, 1.0)))
+            .filter(|(start, end)| start < end);
+    }
+
+    // a one-shot cycle or the loop count has played out and the final value is being held
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // after each reset or trigger, output `idle` (-1.0..1.0 like a waveform value) for `secs`
+    // before the cycle starts, as for delayed vibrato
+    pub fn set_start_delay(&mut self, secs: f64, idle: f64) {
+        self.delay = secs.max(0.0);
+        self.idle = idle.clamp(-1.0, 1.0);
+    }
+
+    // the depth ramps linearly from nothing to full over `secs` after each reset or trigger
+    pub fn set_fade_in(&mut self, secs: f64) {
+        self.fade_in = secs.max(0.0);
+    }
+
+    // the depth dies away after each reset or trigger, for wobbles that settle; 0.0 turns it off
+    pub fn set_decay(&mut self, secs: f64, curve: DecayCurve) {
+        self.decay = secs.max(0.0);
+        self.decay_curve = curve;
+    }
+
+    // for the transport followers: a stopped transport holds the output like freeze() does,
+    // without touching a freeze of the user's own
+    pub(crate) fn set_transport_stopped(&mut self, stopped: bool) {
+        self.transport_stopped = stopped;
+    }
+
+    // holds the last output without advancing, until unfreeze() resumes from the same phase
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    pub fn unfreeze(&mut self) {
+        self.frozen = false;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    // true right after the sample where the phase wrapped, for acting on cycle boundaries
+    pub fn cycle_completed(&self) -> bool {
+        self.wrapped
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // watch the output for crossings of `level` in the `edge` direction; see threshold_crossed()
+    pub fn set_threshold_trigger(&mut self, level: f64, hysteresis: f64, edge: Edge) {
+        let hysteresis = hysteresis.max(0.0);
+        self.threshold = Some(Threshold { level, hysteresis, edge, high: None, fired: false });
+    }
+
+    pub fn clear_threshold_trigger(&mut self) {
+        self.threshold = None;
+    }
+
+    // a one-sample trigger: true right after the sample where the output crossed the threshold
+    pub fn threshold_crossed(&self) -> bool {
+        self.threshold.as_ref().is_some_and(|t| t.fired)
+    }
+
+    // a gate that holds while the output is above the threshold, within the hysteresis
+    pub fn above_threshold(&self) -> bool {
+        self.threshold.as_ref().is_some_and(|t| t.high == Some(true))
+    }
+
+    // restarts the cycle unless free-running
+    pub fn trigger(&mut self) {
+        if self.trigger_mode != TriggerMode::Free {
+            self.reset();
+        }
+    }
+
+    // hard sync: restart the cycle on the next sample, whatever the trigger mode
+    pub fn sync(&mut self) {
+        self.set_phase(0.0);
+    }
+
+    // soft sync: carry on from the current phase in the opposite direction
+    pub fn reverse(&mut self) {
+        // the accumulator is already a step ahead, so take that step back twice
+        self.phase = wrap(self.phase - 2.0 * self.last_step);
+        self.last_step = -self.last_step;
+        self.direction = -self.direction;
+    }
+
+    pub fn hard_sync<S: Signal<Frame = f64>>(self, input: S) -> Synced<S> {
+        self.synced(input, SyncMode::Hard)
+    }
+
+    pub fn soft_sync<S: Signal<Frame = f64>>(self, input: S) -> Synced<S> {
+        self.synced(input, SyncMode::Soft)
+    }
+
+    pub fn synced<S: Signal<Frame = f64>>(self, input: S, mode: SyncMode) -> Synced<S> {
+        Synced { lfo: self, input, mode, high: false }
+    }
+
+    // shifts the accumulator without counting or skipping a cycle, for following an external clock
+    fn nudge_phase(&mut self, amount: f64) {
+        self.phase = wrap(self.phase + amount);
+    }
+
+    // how far the phase is from where a clock at `beat` (in quarter notes) says it should be,
+    // one cycle per rate division, taking the short way round, -0.5..0.5
+    fn beat_error(&self, beat: f64) -> f64 {
+        let target = (beat / self.division.beats()).rem_euclid(1.0);
+        (target - self.phase + 0.5).rem_euclid(1.0) - 0.5
+    }
+
+    // reset so that the sample `sample_offset` samples from now starts the cycle, e.g. for a
+    // note event partway through a processing block; replaces any reset already scheduled
+    pub fn reset_at(&mut self, sample_offset: usize) {
+        self.reset_in = Some(sample_offset);
+    }
+
+    pub fn reset(&mut self) {
+        self.reset_in = None;
+        self.finished = false;
+        self.loops = 0;
+        self.delay_left = (self.delay * self.sample_rate).round();
+        self.elapsed = 0.0;
+        if let Some(sweep) = &mut self.duty_sweep {
+            sweep.elapsed = 0.0;
+        }
+        self.direction = 1.0;
+        self.restarted = true;
+        let phase = self.random_start.as_mut().map_or(0.0, |rng| 0.5 * (rng.next() + 1.0));
+        self.set_phase(phase);
+    }
+
+    // the output together with the same waveform a quarter cycle ahead, e.g. sine and cosine
+    pub fn next_quadrature(&mut self) -> (f64, f64) {
+        let phase = self.offset_phase();
+        let value = self.next();
+        (value, self.tap(phase + 0.25, value))
+    }
+
+    // the output together with a gate or sync stream locked to the same cycle
+    pub fn next_with(&mut self, companion: Companion) -> (f64, f64) {
+        let value = self.next();
+        let high = match companion {
+            Companion::Gate => !self.waiting && self.cycle_phase < 0.5,
+            Companion::Sync => self.started,
+        };
+        (value, if high { 1.0 } else { 0.0 })
+    }
+
+    // the output together with a second reading `offset` cycles behind it, e.g. for a
+    // right channel lagging the left
+    pub fn next_with_tap(&mut self, offset: f64) -> (f64, f64) {
+        let phase = self.offset_phase();
+        let value = self.next();
+        (value, self.tap(phase - offset, value))
+    }
+
+    // `voices` copies of the waveform for chorus-like thickness, their rates spread evenly over
+    // +-`detune` (a fraction of the rate) and starting phases over `spread` cycles; fewer than
+    // two voices turns the ensemble off
+    pub fn set_ensemble(&mut self, voices: usize, detune: f64, spread: f64) {
+        self.ensemble = if voices < 2 {
+            Vec::new()
+        } else {
+            (0..voices).map(|i| {
+                let t = i as f64 / (voices - 1) as f64;
+                (wrap(spread * i as f64 / voices as f64), 1.0 + detune * (2.0 * t - 1.0))
+            }).collect()
+        };
+    }
+
+    // fills `out` with one output per ensemble voice, or with the plain output if there is no ensemble
+    pub fn next_ensemble(&mut self, out: &mut [f64]) {
+        let phase = self.offset_phase();
+        let value = self.next();
+        if self.ensemble.is_empty() {
+            out.fill(value);
+            return;
+        }
+        for (i, slot) in out.iter_mut().enumerate().take(self.ensemble.len()) {
+            *slot = self.tap(phase + self.ensemble[i].0, value);
+        }
+        self.detune_ensemble();
+    }
+
+    // the ensemble voices mixed down, staying inside the output range
+    pub fn next_ensemble_mix(&mut self) -> f64 {
+        let phase = self.offset_phase();
+        let value = self.next();
+        if self.ensemble.is_empty() {
+            return value;
+        }
+        let mut sum = 0.0;
+        for i in 0..self.ensemble.len() {
+            sum += self.tap(phase + self.ensemble[i].0, value);
+        }
+        self.detune_ensemble();
+        sum / self.ensemble.len() as f64
+    }
+
+    // moves each voice's phase offset on by the difference its rate ratio makes to this step
+    fn detune_ensemble(&mut self) {
+        let step = self.last_step;
+        for (offset, ratio) in &mut self.ensemble {
+            *offset = wrap(*offset + (*ratio - 1.0) * step);
+        }
+    }
+
+    // fills `out` with the waveform at evenly spread phases, out[0] being the plain output
+    pub fn next_n_phases(&mut self, out: &mut [f64]) {
+        let phase = self.offset_phase();
+        let value = self.next();
+        let n = out.len() as f64;
+        for (i, slot) in out.iter_mut().enumerate() {
+            *slot = if i == 0 { value } else { self.tap(phase + i as f64 / n, value) };
+        }
+    }
+
+    fn draw(&mut self) {
+        let value = self.rng.next();
+        self.push_random(value);
+    }
+
+    fn push_random(&mut self, value: f64) {
+        self.state.random.rotate_left(1);
+        self.state.random[3] = value;
+    }
+
+    fn next_pink(&mut self) -> f64 {
+        // update the row selected by the number of trailing zeros of the counter
+        self.state.pink_counter = self.state.pink_counter.wrapping_add(1);
+        let row = self.state.pink_counter.trailing_zeros() as usize;
+        if row < self.state.pink.len() {
+            self.state.pink[row] = self.rng.next();
+        }
+        let white = self.rng.next();
+        (self.state.pink.iter().sum::<f64>() + white) / (self.state.pink.len() + 1) as f64 * 3.0
+    }
+
+    fn next_walk(&mut self, step: f64, min: f64, max: f64) -> f64 {
+        let (min, max) = if min <= max { (min, max) } else { (max, min) };
+        let mut walk = self.state.walk + step * self.rng.next();
+        // reflect off the bounds instead of sticking to them
+        if walk > max {
+            walk = max - (walk - max);
+        }
+        if walk < min {
+            walk = min + (min - walk);
+        }
+        self.state.walk = walk.clamp(min, max);
+        self.state.walk
+    }
+
+    fn next_lorenz(&mut self) -> f64 {
+        const SIGMA: f64 = 10.0;
+        const RHO: f64 = 28.0;
+        const BETA: f64 = 8.0 / 3.0;
+        const MAX_DT: f64 = 0.005;
+        // roughly one orbit around a lobe per LFO cycle
+        let dt = 0.7 * self.freq.abs() / self.sample_rate;
+        let substeps = (dt / MAX_DT).ceil().max(1.0);
+        let h = dt / substeps;
+        let [mut x, mut y, mut z] = self.state.lorenz;
+        for _ in 0..substeps as usize {
+            let dx = SIGMA * (y - x);
+            let dy = x * (RHO - z) - y;
+            let dz = x * y - BETA * z;
+            x += h * dx;
+            y += h * dy;
+            z += h * dz;
+        }
+        self.state.lorenz = [x, y, z];
+        // x stays within about +-20 on the attractor
+        (x / 20.0).clamp(-1.0, 1.0)
+    }
+
+    fn next_logistic(&mut self, r: f64) -> f64 {
+        let x = r.clamp(0.0, 4.0) * self.state.logistic * (1.0 - self.state.logistic);
+        // 0.0 and 1.0 are absorbing, so restart from a random point instead of going flat
+        self.state.logistic = if x <= 0.0 || x >= 1.0 {
+            0.5 + 0.49 * self.rng.next()
+        } else {
+            x
+        };
+        self.state.logistic
+    }
+
+    // charge towards +1.0 for the first half of the cycle and discharge towards -1.0 for the second
+    fn next_rc(&mut self, phase: f64, up: f64, down: f64) -> f64 {
+        let (target, tau) = if phase < 0.5 { (1.0, up) } else { (-1.0, down) };
+        let dt = self.freq.abs() / self.sample_rate;
+        self.state.rc += (target - self.state.rc) * -(-dt / tau.max(1e-6)).exp_m1();
+        self.state.rc
+    }
+
+    fn pick_weighted(&mut self, weights: impl Iterator<Item = f64> + Clone) -> usize {
+        let total: f64 = weights.clone().map(|w| w.max(0.0)).sum();
+        let mut r = 0.5 * (self.rng.next() + 1.0) * total;
+        let mut last = 0;
+        for (i, w) in weights.enumerate() {
+            let w = w.max(0.0);
+            if w > 0.0 {
+                if r < w {
+                    return i;
+                }
+                last = i;
+            }
+            r -= w;
+        }
+        last
+    }
+
+    // Ornstein-Uhlenbeck style walk: a spring pulls towards `center` while noise pushes it around,
+    // both scaled so one LFO cycle is one unit of time
+    fn next_wander(&mut self, center: f64, stiffness: f64, noise: f64) -> f64 {
+        let dt = self.freq.abs() / self.sample_rate;
+        let kick = noise * (3.0 * dt).sqrt() * self.rng.next();
+        self.state.walk += stiffness.max(0.0) * (center - self.state.walk) * dt + kick;
+        self.state.walk = self.state.walk.clamp(-1.0, 1.0);
+        self.state.walk
+    }
+
+    // Box-Muller on two uniform draws, clamped to the waveform range
+    fn next_gaussian(&mut self, sigma: f64) -> f64 {
+        let u1 = 0.5 * (self.rng.next() + 1.0);
+        let u2 = 0.5 * (self.rng.next() + 1.0);
+        let z = (-2.0 * (1.0 - u1).ln()).sqrt() * (TAU * u2).cos();
+        (sigma * z).clamp(-1.0, 1.0)
+    }
+
+    fn next_brown(&mut self) -> f64 {
+        // leaky integration keeps the walk from sticking to the rails
+        self.state.brown = 0.95 * self.state.brown + 0.3 * self.rng.next();
+        self.state.brown
+    }
+
+    // everything shape() keeps per waveform in the tree is set up here rather than on the
+    // audio thread: one state per nested waveform and the rendered spline tables
+    fn prepare_waveform(&mut self) {
+        self.nest_states();
+        self.spline_tables.clear();
+        self.spline_tables.resize(tree_size(&self.waveform), Vec::new());
+        render_splines(&self.waveform, 0, &mut self.spline_tables);
+    }
+
+    fn nest_states(&mut self) {
+        self.nested.clear();
+        for _ in 1..tree_size(&self.waveform) {
+            let random = [self.rng.next(), self.rng.next(), self.rng.next(), self.rng.next()];
+            self.nested.push(ShapeState::new(random));
+        }
+    }
+
+    // shapes the child at tree position `node` with that child's own running state
+    fn shape_child(&mut self, waveform: &mut Waveform, phase: f64, node: usize) -> f64 {
+        std::mem::swap(&mut self.state, &mut self.nested[node - 1]);
+        let value = self.shape(waveform, phase, node);
+        std::mem::swap(&mut self.state, &mut self.nested[node - 1]);
+        value
+    }
+
+    // `node` is the waveform's position in the tree, 0 for the LFO's own waveform
+    fn shape(&mut self, waveform: &mut Waveform, phase: f64, node: usize) -> f64 {
+        match *waveform {
+            Waveform::Sine => {
+                sine(phase)
+            },
+            Waveform::Triangle => {
+                triangle(phase)
+            },
+            Waveform::SawUp => {
+                saw(phase, true)
+            },
+            Waveform::SawDn => {
+                saw(phase, false)
+            }
+            Waveform::Pulse(duty_ratio) => {
+                let duty_ratio = self.duty_sweep.as_ref().map_or(duty_ratio, |sweep| sweep.duty());
+                pulse(phase, (duty_ratio + self.dm).clamp(0.0, 1.0))
+            },
+            Waveform::SampleHold(steps) => {
+                if self.crossed(phase, steps) {
+                    self.draw();
+                }
+                self.state.random[3]
+            },
+            Waveform::RandomSmooth(interpolation) => {
+                if self.crossed(phase, 1) {
+                    self.draw();
+                }
+                interpolate(&self.state.random, phase, interpolation)
+            },
+            Waveform::Perlin { octaves, persistence } => {
+                perlin(self.seed, self.cycles as f64 + phase, octaves, persistence)
+            },
+            Waveform::PinkNoise => {
+                if self.crossed(phase, 1) {
+                    let value = self.next_pink();
+                    self.push_random(value.clamp(-1.0, 1.0));
+                }
+                interpolate(&self.state.random, phase, Interpolation::Linear)
+            },
+            Waveform::BrownNoise => {
+                if self.crossed(phase, 1) {
+                    let value = self.next_brown();
+                    self.push_random(value.clamp(-1.0, 1.0));
+                }
+                interpolate(&self.state.random, phase, Interpolation::Linear)
+            },
+            Waveform::Drunk { step, min, max } => {
+                if self.crossed(phase, 1) {
+                    self.next_walk(step, min, max);
+                }
+                self.state.walk.clamp(min.min(max), max.max(min))
+            },
+            Waveform::ExpSaw(tau) => {
+                exp_saw(phase, tau)
+            },
+            Waveform::SineHalf => {
+                sine_half(phase)
+            },
+            Waveform::SineFullRect => {
+                sine_full_rect(phase)
+            },
+            Waveform::SineQuarter => {
+                sine_quarter(phase)
+            },
+            Waveform::PowerSine(exponent) => {
+                power_sine(phase, exponent)
+            },
+            Waveform::Trapezoid { rise, fall } => {
+                trapezoid(phase, rise, fall)
+            },
+            Waveform::Stairs { steps, base } => {
+                stairs(phase, steps, base)
+            },
+            Waveform::StepSeq { ref levels, glide } => {
+                step_seq(phase, levels, glide)
+            },
+            Waveform::Euclid { pulses, steps, rotation } => {
+                euclid(phase, pulses, steps, rotation)
+            },
+            Waveform::Lorenz => {
+                self.next_lorenz()
+            },
+            Waveform::Logistic { r } => {
+                if self.crossed(phase, 1) {
+                    self.next_logistic(r);
+                }
+                2.0 * self.state.logistic - 1.0
+            },
+            Waveform::Harmonics(ref partials) => {
+                harmonics(phase, partials)
+            },
+            Waveform::Wavetable { ref table, interpolation } => {
+                wavetable(phase, table, interpolation)
+            },
+            Waveform::WavetableBank { ref tables, interpolation } => {
+                wavetable_bank(phase, tables, self.morph, interpolation)
+            },
+            Waveform::Segments(ref points) => {
+                segments(phase, points)
+            },
+            Waveform::Spline(_) => {
+                wavetable(phase, &self.spline_tables[node], Interpolation::Linear)
+            },
+            Waveform::Custom(ref mut f) => {
+                f(phase)
+            },
+            Waveform::Bump { width, shape } => {
+                bump(phase, width, shape)
+            },
+            Waveform::BouncingBall { bounces, damping } => {
+                bouncing_ball(phase, bounces, damping)
+            },
+            Waveform::SkewTriangle(symmetry) => {
+                skew_triangle(phase, symmetry)
+            },
+            Waveform::CurvedSaw { bend, ramp_up } => {
+                curved_saw(phase, bend, ramp_up)
+            },
+            Waveform::Pattern(bits, length) => {
+                pattern(phase, bits, length)
+            },
+            Waveform::Parabolic => {
+                parabolic(phase)
+            },
+            Waveform::Mix(ref mut a, ref mut b, amount) => {
+                let size = tree_size(a);
+                let a = self.shape_child(a, phase, node + 1);
+                let b = self.shape_child(b, phase, node + 1 + size);
+                a + (b - a) * amount.clamp(0.0, 1.0)
+            },
+            Waveform::Chirp { start, end } => {
+                chirp(phase, start, end)
+            },
+            Waveform::Ease(easing) => {
+                eased(phase, easing)
+            },
+            Waveform::RC { up, down } => {
+                self.next_rc(phase, up, down)
+            },
+            Waveform::Choose(ref mut options) => {
+                if options.is_empty() {
+                    return 0.0;
+                }
+                if self.state.choice.is_none() || self.crossed(phase, 1) {
+                    self.state.choice = Some(self.pick_weighted(options.iter().map(|(_, w)| *w)));
+                }
+                let i = self.state.choice.unwrap_or(0).min(options.len() - 1);
+                let child = node + 1 + options[..i].iter().map(|(w, _)| tree_size(w)).sum::<usize>();
+                self.shape_child(&mut options[i].0, phase, child)
+            },
+            Waveform::Chain(ref mut chain) => {
+                let n = chain.waveforms.len();
+                if n == 0 {
+                    return 0.0;
+                }
+                let current = self.state.choice.unwrap_or(0).min(n - 1);
+                let next = match chain.transitions.get(current) {
+                    Some(row) if self.state.choice.is_some() && self.crossed(phase, 1) => {
+                        // a row without any positive weight stays put
+                        if row.iter().take(n).any(|w| *w > 0.0) {
+                            self.pick_weighted(row.iter().take(n).copied())
+                        } else {
+                            current
+                        }
+                    },
+                    _ => current,
+                };
+                self.state.choice = Some(next);
+                let child = node + 1 + chain.waveforms[..next].iter().map(tree_size).sum::<usize>();
+                self.shape_child(&mut chain.waveforms[next], phase, child)
+            },
+            Waveform::Wander { center, stiffness, noise } => {
+                self.next_wander(center, stiffness, noise)
+            },
+            Waveform::Gaussian { sigma, interpolation } => {
+                if self.crossed(phase, 1) {
+                    let value = self.next_gaussian(sigma);
+                    self.push_random(value);
+                }
+                interpolate(&self.state.random, phase, interpolation)
+            },
+        }
+    }
+
+    // whether the cycle now ending is the last one to play before holding
+    fn last_cycle(&self) -> bool {
+        self.trigger_mode == TriggerMode::OneShot || self.loop_count.is_some_and(|n| self.loops + 1 >= n)
+    }
+
+    fn crossed(&self, phase: f64, steps: u32) -> bool {
+        crossed_step(self.last_phase, phase, steps, self.forward)
+    }
+
+    // the accumulated phase with the theta offset and any analog drift applied
+    fn offset_phase(&self) -> f64 {
+        let phase = phase(self.phase, self.theta);
+        match &self.analog {
+            Some(analog) => (phase + analog.offset).rem_euclid(1.0),
+            None => phase,
+        }
+    }
+
+    fn render(&mut self, phase: f64) -> f64 {
+        let phase = self.warp(phase);
+        let mut waveform = std::mem::replace(&mut self.waveform, Waveform::Sine);
+        let value = self.shape(&mut waveform, phase, 0);
+        self.waveform = waveform;
+        self.last_phase = phase;
+        let value = match &self.analog {
+            Some(analog) => (value * analog.amplitude()).clamp(-1.0, 1.0),
+            None => value,
+        };
+        value * self.envelope()
+    }
+
+    // depth scaling since the last reset
+    fn envelope(&self) -> f64 {
+        let fade_in = if self.elapsed < self.fade_in {
+            self.elapsed / self.fade_in
+        } else {
+            1.0
+        };
+        let decay = match self.decay_curve {
+            _ if self.decay == 0.0 => 1.0,
+            DecayCurve::Linear => (1.0 - self.elapsed / self.decay).max(0.0),
+            DecayCurve::Exponential => (-self.elapsed / self.decay).exp(),
+        };
+        fade_in * decay
+    }
+
+    // phase as the waveform sees it, after ratcheting, time warp, swing, plateaus and phase modulation
+    fn warp(&self, phase: f64) -> f64 {
+        let phase = if self.ratchet > 1 {
+            (phase * self.ratchet as f64).fract()
+        } else {
+            phase
+        };
+        let phase = self.time_warp.map_or(phase, |warp| time_warp(phase, warp));
+        let phase = if self.swing > 0.0 { swing(phase, self.swing) } else { phase };
+        let phase = match (self.plateau, self.extremes) {
+            ((0.0, 0.0), _) => phase,
+            ((top, bottom), (max, min)) if max <= min => plateau(phase, &[(max, top), (min, bottom)]),
+            ((top, bottom), (max, min)) => plateau(phase, &[(min, bottom), (max, top)]),
+        };
+        let phase = if self.distortion > 0.0 { phase_distortion(phase, self.distortion) } else { phase };
+        wrap(phase + self.pm)
+    }
+
+    // the waveform at another phase of the current cycle, leaving any running state untouched
+    fn peek(&mut self, phase: f64) -> f64 {
+        self.undisturbed(|lfo| {
+            // with no step crossed, stepped and random shapes read their current values
+            lfo.last_phase = lfo.warp(phase);
+            lfo.render(phase)
+        })
+    }
+
+    // phases of the waveform's maximum and minimum over one cycle, sampled on a grid
+    fn find_extremes(&mut self) -> (f64, f64) {
+        const GRID: usize = 1024;
+        self.undisturbed(|lfo| {
+            let mut waveform = std::mem::replace(&mut lfo.waveform, Waveform::Sine);
+            let (mut max, mut min) = ((f64::MIN, 0.0), (f64::MAX, 0.0));
+            for i in 0..GRID {
+                let phase = i as f64 / GRID as f64;
+                lfo.last_phase = phase;
+                let value = lfo.shape(&mut waveform, phase, 0);
+                if value > max.0 {
+                    max = (value, phase);
+                }
+                if value < min.0 {
+                    min = (value, phase);
+                }
+            }
+            lfo.waveform = waveform;
+            (max.1, min.1)
+        })
+    }
+
+    // runs `f` and then puts back whatever running state the waveforms keep
+    fn undisturbed<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let rng = self.rng.clone();
+        let (state, last_phase) = (self.state, self.last_phase);
+        let mut saved = std::mem::take(&mut self.saved);
+        saved.clear();
+        saved.extend_from_slice(&self.nested);
+        let value = f(self);
+        self.rng = rng;
+        (self.state, self.last_phase) = (state, last_phase);
+        self.nested.clear();
+        self.nested.extend_from_slice(&saved);
+        self.saved = saved;
+        value
+    }
+
+    // output at `phase` alongside the main output `value`, which it follows while held
+    fn tap(&mut self, phase: f64, value: f64) -> f64 {
+        if self.finished || self.frozen || self.transport_stopped || self.waiting || self.skipping {
+            return value;
+        }
+        let value = self.peek(wrap(phase));
+        self.output(value)
+    }
+
+    // maps the bipolar waveform onto the output range
+    fn output(&self, value: f64) -> f64 {
+        let value = if self.invert { -value } else { value };
+        let value = match self.steps {
+            Some(steps) => quantize_steps(value + self.dither_offset, steps),
+            None => value,
+        };
+        let value = self.gain * value;
+        let value = match self.shaper {
+            Some((shaper, drive)) => shape_output(value, shaper, drive),
+            None => value,
+        };
+        let value = if value.abs() < self.dead_zone { 0.0 } else { value };
+        let value = match self.output_mode {
+            OutputMode::Bipolar => value,
+            OutputMode::Unipolar => 0.5 * (value + self.gain),
+            OutputMode::InvertedUnipolar => 0.5 * (self.gain - value),
+        };
+        let value = match self.output_range {
+            Some((min, max)) => {
+                let position = match self.output_mode {
+                    OutputMode::Bipolar => 0.5 * (value + 1.0),
+                    _ => value,
+                };
+                map_range(position, min, max, self.range_curve)
+            },
+            None => value,
+        };
+        let value = value + self.offset;
+        let value = match self.clamp {
+            Some((min, max)) => value.clamp(min, max),
+            None => value,
+        };
+        match self.scale {
+            Some(scale) => scale.quantize(value),
+            None => value,
+        }
+    }
+
+    // works out this sample's dither from the waveform `value` before it reaches output()
+    fn update_dither(&mut self, value: f64) {
+        let (Some(dither), Some(steps)) = (self.dither, self.steps) else {
+            self.dither_offset = 0.0;
+            return;
+        };
+        match dither {
+            Dither::Tpdf => {
+                let step = 2.0 / (steps.max(2) as f64 - 1.0);
+                self.dither_offset = 0.5 * (self.dither_rng.next() + self.dither_rng.next()) * step;
+            },
+            Dither::ErrorFeedback => {
+                let value = if self.invert { -value } else { value };
+                self.dither_offset = -self.dither_error;
+                let target = value + self.dither_offset;
+                self.dither_error = quantize_steps(target, steps) - target;
+            },
+        }
+    }
+
+    // the stages after output() that depend on what came out before
+    fn filter_output(&mut self, value: f64) -> f64 {
+        let value = match self.held {
+            Some(held) if (value - held).abs() <= self.hysteresis => held,
+            _ => value,
+        };
+        if self.hysteresis > 0.0 {
+            self.held = Some(value);
+        }
+        let Some(last) = self.last_output else {
+            self.last_output = Some(value);
+            return value;
+        };
+        let value = match self.slew {
+            Some((rise, fall)) => value.clamp(last - fall / self.sample_rate, last + rise / self.sample_rate),
+            None => value,
+        };
+        let value = if self.smoothing > 0.0 {
+            last + (value - last) * (1.0 - (-1.0 / (self.smoothing * self.sample_rate)).exp())
+        } else {
+            value
+        };
+        self.last_output = Some(value);
+        value
+    }
+
+    fn follow_tempo(&mut self) {
+        let k = 1.0 - (-1.0 / (self.tempo_follow * self.sample_rate)).exp();
+        self.freq += (self.tempo_freq - self.freq) * k;
+        if (self.tempo_freq - self.freq).abs() <= 1e-9 * self.tempo_freq.abs() {
+            self.freq = self.tempo_freq;
+        }
+    }
+
+    fn generate(&mut self) -> f64 {
+        match self.reset_in {
+            Some(0) => self.reset(),
+            Some(n) => self.reset_in = Some(n - 1),
+            None => {},
+        }
+        self.wrapped = false;
+        self.started = false;
+        if self.finished || self.frozen || self.transport_stopped {
+            return self.last_value;
+        }
+        self.waiting = self.delay_left > 0.0;
+        if self.waiting {
+            self.delay_left -= 1.0;
+            return self.idle;
+        }
+        let phase = self.offset_phase();
+        // the rate may swing through zero under FM, so which way the cycle wraps follows
+        // the sign of the step that got here
+        self.forward = self.last_step >= 0.0;
+        if self.tempo.is_some() && self.freq != self.tempo_freq {
+            self.follow_tempo();
+        }
+        let fm = self.freq_mod.as_mut().map_or(0.0, |m| m.next());
+        self.last_step = self.direction * (self.freq + fm) / self.sample_rate;
+        let mut next = self.phase + self.last_step;
+        if let Some((start, end)) = self.loop_points {
+            if self.last_step > 0.0 && self.phase < end && next >= end {
+                next = start + (next - end);
+            } else if self.last_step < 0.0 && self.phase > start && next <= start {
+                next = end - (start - next);
+            }
+        }
+        self.phase = wrap(next);
+        let wrapped = if self.forward { phase < self.cycle_phase } else { phase > self.cycle_phase };
+        self.cycle_phase = phase;
+        self.wrapped = wrapped;
+        self.started = wrapped || std::mem::take(&mut self.restarted);
+        if wrapped && self.last_cycle() {
+            // hold the value the cycle ends on, not just its last sample
+            self.finished = true;
+            if !self.skipping {
+                let end = if self.forward { 1.0 - f64::EPSILON } else { 0.0 };
+                self.last_value = self.render(end);
+            }
+            return self.last_value;
+        }
+        if wrapped {
+            self.cycles += 1;
+            self.loops = self.loops.saturating_add(1);
+            self.ratchet = std::mem::replace(&mut self.ratchet_pending, 1);
+            if self.skip_probability > 0.0 {
+                self.skipping = 0.5 * (self.rng.next() + 1.0) < self.skip_probability;
+            }
+        }
+        if let Some(analog) = &mut self.analog {
+            analog.tick(self.freq, self.sample_rate, wrapped);
+        }
+        if let Some(modulation) = &mut self.phase_mod {
+            self.pm = modulation.next();
+        }
+        if let Some(modulation) = &mut self.duty_mod {
+            self.dm = modulation.next();
+        }
+        let value = self.render(phase);
+        self.elapsed += 1.0 / self.sample_rate;
+        if let Some(sweep) = &mut self.duty_sweep {
+            sweep.elapsed += 1.0 / self.sample_rate;
+        }
+        if self.skipping {
+            // the waveform above still runs so stateful shapes stay in step
+            return match self.skip_mode {
+                SkipMode::Hold => self.last_value,
+                SkipMode::Center => 0.0,
+            };
+        }
+        self.last_value = value;
+        value
+    }
+}
+
+// syncs the wrapped LFO on every rising edge of `input` through 0.5
+pub struct Synced<S> {
+    lfo: LFO,
+    input: S,
+    mode: SyncMode,
+    high: bool,
+}
+
+impl<S> Synced<S> {
+    pub fn lfo_mut(&mut self) -> &mut LFO {
+        &mut self.lfo
+    }
+
+    pub fn into_inner(self) -> (LFO, S) {
+        (self.lfo, self.input)
+    }
+}
+
+impl<S: Signal<Frame = f64>> Signal for Synced<S> {
+    type Frame = f64;
+
+    fn next(&mut self) -> Self::Frame {
+        let high = self.input.next() > 0.5;
+        if high && !self.high {
+            match self.mode {
+                SyncMode::Hard => self.lfo.sync(),
+                SyncMode::Soft => self.lfo.reverse(),
+            }
+        }
+        self.high = high;
+        self.lfo.next()
+    }
+}
+
+// LFOs at whole-number ratios of a shared master cycle, e.g. 3:2 for three cycles every two of
+// the master's; each member's phase is worked out from the master's cycle count rather than
+// accumulated on its own, so the group never drifts apart
+pub struct LfoGroup {
+    freq: f64,
+    sample_rate: f64,
+    phase: f64, // of the master
+    cycles: i64, // whole master cycles, negative when it has run backwards past the start
+    members: Vec<(LFO, u32, u32)>,
+}
+
+impl LfoGroup {
+    pub fn new(freq: f64, sample_rate: f64) -> Self {
+        LfoGroup { freq, sample_rate, phase: 0.0, cycles: 0, members: Vec::new() }
+    }
+
+    // `cycles` cycles of `lfo` for every `per` master cycles; returns the member's index. The
+    // member's own rate is replaced, its sample rate should match the group's
+    pub fn add(&mut self, mut lfo: LFO, cycles: u32, per: u32) -> usize {
+        let per = per.max(1);
+        lfo.set_freq(self.freq * cycles as f64 / per as f64);
+        self.members.push((lfo, cycles, per));
+        self.members.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn lfo_mut(&mut self, index: usize) -> &mut LFO {
+        &mut self.members[index].0
+    }
+
+    pub fn set_freq(&mut self, freq: f64) {
+        self.freq = freq;
+        for (lfo, cycles, per) in &mut self.members {
+            lfo.set_freq(freq * *cycles as f64 / *per as f64);
+        }
+    }
+
+    pub fn phase(&self) -> f64 {
+        self.phase
+    }
+
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+        self.cycles = 0;
+        for (lfo, _, _) in &mut self.members {
+            lfo.reset();
+        }
+    }
+
+    // fills `out` with one output per member, in the order they were added
+    pub fn next_frame(&mut self, out: &mut [f64]) {
+        for (slot, (lfo, cycles, per)) in out.iter_mut().zip(&mut self.members) {
+            // only the master cycle count modulo `per` matters, which keeps the sum small and exact
+            let master = self.cycles.rem_euclid(*per as i64) as f64 + self.phase;
+            let target = (master * *cycles as f64 / *per as f64).fract();
+            lfo.nudge_phase((target - lfo.phase + 0.5).rem_euclid(1.0) - 0.5);
+            *slot = lfo.next();
+        }
+        let next = self.phase + self.freq / self.sample_rate;
+        self.cycles += next.floor() as i64;
+        self.phase = wrap(next);
+    }
+}
+
+impl Signal for LFO {
+    type Frame = f64;
+
+    fn next(&mut self) -> Self::Frame {
+        let value = self.generate();
+        self.update_dither(value);
+        let value = self.output(value);
+        let value = self.filter_output(value);
+        if let Some(threshold) = &mut self.threshold {
+            threshold.update(value);
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plotters::prelude::*;
+
+    fn take(lfo: &mut LFO, n: usize) -> Vec<f64> {
+        (0..n).map(|_| lfo.next()).collect()
+    }
+
+    fn create_chart(lfo: &mut LFO, t_sec: f64, filename: &str, cap: &str) {
+        let sample_rate = lfo.sample_rate;
+        chart_signal(lfo, sample_rate, t_sec, filename, cap);
+    }
+
+    fn chart_signal<S: Signal<Frame = f64>>(lfo: &mut S, sample_rate: f64, t_sec: f64, filename: &str, cap: &str) {
+        let data_len: usize = (sample_rate * t_sec) as usize;
+        let lfo_vec: Vec<f64> = (0..=data_len).map(|_i| {
+            lfo.next()
+        }).collect();
 
         let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
 
-        root.fill(&WHITE).unwrap();
+        root.fill(&WHITE).unwrap();
+
+        let mut chart = ChartBuilder::on(&root)
+            .set_label_area_size(LabelAreaPosition::Left, 60)
+            .set_label_area_size(LabelAreaPosition::Bottom, 60)
+            .caption(cap, ("sans-serif", 40))
+            .build_cartesian_2d(-0.1_f64..t_sec+0.1, -1.1_f64..1.1_f64)
+            .unwrap();
+
+        chart
+            .configure_mesh()
+            //.disable_x_mesh()
+            //.disable_y_mesh()
+            .draw()
+            .unwrap();
+
+        chart.draw_series(
+            AreaSeries::new(
+                (0..=data_len).zip(lfo_vec.iter()).map(|(x, y)| (x as f64 / sample_rate, *y)),
+                0.0,
+                RED.mix(0.2),
+            )
+            .border_style(RED),
+        ).unwrap();
+
+        // To avoid the IO failure being ignored silently, we manually call the present function
+        root.present().unwrap();
+    }
+
+    #[test]
+    fn sine_10hz() {
+        let mut lfo = LFO::new(Waveform::Sine, 10.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/sine_10hz.png", "sine_10hz");
+    }
+
+    #[test]
+    fn triangle_3hz() {
+        let mut lfo = LFO::new(Waveform::Triangle, 3.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/triangle_3hz.png", "triangle_3hz");
+    }
+
+    #[test]
+    fn sawup_5hz() {
+        let mut lfo = LFO::new(Waveform::SawUp, 5.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/sawup_5hz.png", "sawup_5hz");
+    }
+
+    #[test]
+    fn sawdn_5hz() {
+        let mut lfo = LFO::new(Waveform::SawDn, 5.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/sawdn_5hz.png", "sawdn_5hz");
+    }
+
+    #[test]
+    fn pulse_25percent_5hz() {
+        let mut lfo = LFO::new(Waveform::Pulse(0.25), 5.0, 1000.0);
+        lfo.set_gain(0.5);
+        create_chart(&mut lfo, 1.0, "chart/pulse_25percent_2hz.png", "pulse_25percent_2hz");
+    }
+
+    #[test]
+    fn sample_hold_4hz() {
+        let mut lfo = LFO::new(Waveform::SampleHold(2), 4.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/sample_hold_4hz.png", "sample_hold_4hz");
+    }
+
+    #[test]
+    fn random_smooth_cosine_2hz() {
+        let mut lfo = LFO::new(Waveform::RandomSmooth(Interpolation::Cosine), 2.0, 1000.0);
+        create_chart(&mut lfo, 2.0, "chart/random_smooth_cosine_2hz.png", "random_smooth_cosine_2hz");
+    }
+
+    #[test]
+    fn random_smooth_is_continuous_across_cycles() {
+        for interpolation in [Interpolation::Linear, Interpolation::Cosine, Interpolation::Cubic] {
+            let mut lfo = LFO::new(Waveform::RandomSmooth(interpolation), 5.0, 1000.0);
+            let out: Vec<f64> = (0..1000).map(|_| lfo.next()).collect();
+            assert!(out.windows(2).all(|w| (w[1] - w[0]).abs() < 0.05));
+        }
+    }
+
+    #[test]
+    fn perlin_2hz() {
+        let mut lfo = LFO::new(Waveform::Perlin { octaves: 4, persistence: 0.5 }, 2.0, 1000.0);
+        create_chart(&mut lfo, 2.0, "chart/perlin_2hz.png", "perlin_2hz");
+    }
+
+    #[test]
+    fn pink_noise_50hz() {
+        let mut lfo = LFO::new(Waveform::PinkNoise, 50.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/pink_noise_50hz.png", "pink_noise_50hz");
+    }
+
+    #[test]
+    fn brown_noise_50hz() {
+        let mut lfo = LFO::new(Waveform::BrownNoise, 50.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/brown_noise_50hz.png", "brown_noise_50hz");
+    }
+
+    #[test]
+    fn drunk_20hz() {
+        let mut lfo = LFO::new(Waveform::Drunk { step: 0.2, min: -0.5, max: 1.0 }, 20.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/drunk_20hz.png", "drunk_20hz");
+    }
+
+    #[test]
+    fn drunk_stays_within_bounds() {
+        let mut lfo = LFO::new(Waveform::Drunk { step: 0.5, min: -0.2, max: 0.4 }, 100.0, 1000.0);
+        assert!((0..10_000).map(|_| 2.0 * lfo.next() - 1.0).all(|v| (-0.2..=0.4).contains(&v)));
+    }
+
+    #[test]
+    fn exp_saw_5hz() {
+        let mut lfo = LFO::new(Waveform::ExpSaw(0.2), 5.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/exp_saw_5hz.png", "exp_saw_5hz");
+    }
+
+    #[test]
+    fn sine_half_5hz() {
+        let mut lfo = LFO::new(Waveform::SineHalf, 5.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/sine_half_5hz.png", "sine_half_5hz");
+    }
+
+    #[test]
+    fn sine_full_rect_5hz() {
+        let mut lfo = LFO::new(Waveform::SineFullRect, 5.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/sine_full_rect_5hz.png", "sine_full_rect_5hz");
+    }
+
+    #[test]
+    fn sine_quarter_5hz() {
+        let mut lfo = LFO::new(Waveform::SineQuarter, 5.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/sine_quarter_5hz.png", "sine_quarter_5hz");
+    }
+
+    #[test]
+    fn power_sine_5hz() {
+        let mut lfo = LFO::new(Waveform::PowerSine(0.25), 5.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/power_sine_5hz.png", "power_sine_5hz");
+    }
+
+    #[test]
+    fn trapezoid_3hz() {
+        let mut lfo = LFO::new(Waveform::Trapezoid { rise: 0.1, fall: 0.3 }, 3.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/trapezoid_3hz.png", "trapezoid_3hz");
+    }
+
+    #[test]
+    fn trapezoid_generalizes_triangle_and_pulse() {
+        for i in 0..100 {
+            let phase = i as f64 / 100.0;
+            assert!((trapezoid(phase, 0.5, 0.5) - triangle(phase)).abs() < 1e-9);
+            assert_eq!(trapezoid(phase, 0.0, 0.0), pulse(phase, 0.5));
+        }
+    }
+
+    #[test]
+    fn stairs_triangle_2hz() {
+        let mut lfo = LFO::new(Waveform::Stairs { steps: 8, base: StairBase::Triangle }, 2.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/stairs_triangle_2hz.png", "stairs_triangle_2hz");
+    }
+
+    #[test]
+    fn stairs_saw_spans_full_range() {
+        assert_eq!(stairs(0.0, 4, StairBase::SawUp), -1.0);
+        assert_eq!(stairs(0.99, 4, StairBase::SawUp), 1.0);
+        assert_eq!(stairs(0.0, 4, StairBase::SawDn), 1.0);
+    }
+
+    #[test]
+    fn step_seq_1hz() {
+        let levels = vec![-1.0, 0.5, -0.25, 1.0, 0.0, -0.75, 0.75, -0.5];
+        let mut lfo = LFO::new(Waveform::StepSeq { levels, glide: 0.25 }, 1.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/step_seq_1hz.png", "step_seq_1hz");
+    }
+
+    #[test]
+    fn step_seq_glides_into_next_step() {
+        let levels = [-1.0, 1.0];
+        assert_eq!(step_seq(0.1, &levels, 0.5), -1.0);
+        assert!((step_seq(0.375, &levels, 0.5) - 0.0).abs() < 1e-9);
+        assert_eq!(step_seq(0.6, &levels, 0.0), 1.0);
+        assert_eq!(step_seq(0.5, &[], 0.5), 0.0);
+    }
+
+    #[test]
+    fn euclid_3_8_1hz() {
+        let mut lfo = LFO::new(Waveform::Euclid { pulses: 3, steps: 8, rotation: 0 }, 1.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/euclid_3_8_1hz.png", "euclid_3_8_1hz");
+    }
+
+    #[test]
+    fn euclid_spreads_pulses_evenly() {
+        let pattern = |rotation| -> Vec<bool> {
+            (0..8).map(|i| euclid((i as f64 + 0.5) / 8.0, 3, 8, rotation) > 0.0).collect()
+        };
+        assert_eq!(pattern(0), [true, false, false, true, false, false, true, false]);
+        assert_eq!(pattern(1), [false, false, true, false, false, true, false, true]);
+    }
+
+    #[test]
+    fn lorenz_2hz() {
+        let mut lfo = LFO::new(Waveform::Lorenz, 2.0, 1000.0);
+        create_chart(&mut lfo, 4.0, "chart/lorenz_2hz.png", "lorenz_2hz");
+    }
+
+    #[test]
+    fn logistic_20hz() {
+        let mut lfo = LFO::new(Waveform::Logistic { r: 3.9 }, 20.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/logistic_20hz.png", "logistic_20hz");
+    }
+
+    #[test]
+    fn logistic_does_not_get_stuck() {
+        let mut lfo = LFO::new(Waveform::Logistic { r: 4.0 }, 100.0, 1000.0);
+        lfo.state.logistic = 0.5;
+        let out: Vec<f64> = (0..1000).map(|_| lfo.next()).collect();
+        assert!(out[500..].windows(20).any(|w| w[0] != w[19]));
+    }
+
+    #[test]
+    fn harmonics_2hz() {
+        let partials = vec![(1.0, 0.0), (0.0, 0.0), (0.33, 0.0), (0.0, 0.0), (0.2, 0.0)];
+        let mut lfo = LFO::new(Waveform::Harmonics(partials), 2.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/harmonics_2hz.png", "harmonics_2hz");
+    }
+
+    #[test]
+    fn harmonics_single_partial_is_sine() {
+        for i in 0..100 {
+            let phase = i as f64 / 100.0;
+            assert!((harmonics(phase, &[(0.5, 0.0)]) - sine(phase)).abs() < 1e-12);
+        }
+        assert_eq!(harmonics(0.3, &[]), 0.0);
+    }
+
+    #[test]
+    fn wavetable_cubic_2hz() {
+        let table = vec![0.0, 1.0, 0.2, 0.6, -0.4, -1.0, -0.1, -0.5];
+        let mut lfo = LFO::new(Waveform::Wavetable { table, interpolation: Interpolation::Cubic }, 2.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/wavetable_cubic_2hz.png", "wavetable_cubic_2hz");
+    }
+
+    #[test]
+    fn wavetable_interpolates_between_samples() {
+        let table = [-1.0, 1.0];
+        assert_eq!(wavetable(0.25, &table, Interpolation::None), -1.0);
+        assert_eq!(wavetable(0.25, &table, Interpolation::Linear), 0.0);
+        assert_eq!(wavetable(0.75, &table, Interpolation::Linear), 0.0);
+        assert_eq!(wavetable(0.5, &table, Interpolation::Cubic), 1.0);
+        assert_eq!(wavetable(0.5, &[], Interpolation::Linear), 0.0);
+    }
+
+    #[test]
+    fn wavetable_bank_morph_2hz() {
+        let sine_table: Vec<f64> = (0..64).map(|i| sine(i as f64 / 64.0)).collect();
+        let pulse_table: Vec<f64> = (0..64).map(|i| pulse(i as f64 / 64.0, 0.5)).collect();
+        let tables = vec![sine_table, pulse_table];
+        let mut lfo = LFO::new(Waveform::WavetableBank { tables, interpolation: Interpolation::Linear }, 2.0, 1000.0);
+        lfo.set_morph(0.5);
+        create_chart(&mut lfo, 1.0, "chart/wavetable_bank_morph_2hz.png", "wavetable_bank_morph_2hz");
+    }
+
+    #[test]
+    fn wavetable_bank_crossfades_adjacent_tables() {
+        let tables = vec![vec![-1.0], vec![0.0], vec![1.0]];
+        assert_eq!(wavetable_bank(0.5, &tables, 0.0, Interpolation::None), -1.0);
+        assert_eq!(wavetable_bank(0.5, &tables, 0.25, Interpolation::None), -0.5);
+        assert_eq!(wavetable_bank(0.5, &tables, 0.75, Interpolation::None), 0.5);
+        assert_eq!(wavetable_bank(0.5, &tables, 1.0, Interpolation::None), 1.0);
+    }
+
+    #[test]
+    fn segments_2hz() {
+        let points = vec![
+            Breakpoint::new(0.0, -1.0, 0.5),
+            Breakpoint::new(0.2, 1.0, -0.5),
+            Breakpoint::new(0.5, 0.0, 0.0),
+            Breakpoint::new(0.7, 0.0, 0.8),
+        ];
+        let mut lfo = LFO::new(Waveform::Segments(points), 2.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/segments_2hz.png", "segments_2hz");
+    }
+
+    #[test]
+    fn segments_wrap_across_cycle_end() {
+        let points = [Breakpoint::new(0.25, -1.0, 0.0), Breakpoint::new(0.75, 1.0, 0.0)];
+        assert_eq!(segments(0.25, &points), -1.0);
+        assert_eq!(segments(0.5, &points), 0.0);
+        assert_eq!(segments(0.0, &points), 0.0);
+        assert_eq!(segments(0.875, &points), 0.5);
+        assert_eq!(segments(0.5, &[]), 0.0);
+    }
+
+    #[test]
+    fn spline_2hz() {
+        let points = vec![(0.0, -1.0), (0.15, 0.8), (0.4, 0.2), (0.6, 1.0), (0.8, -0.3)];
+        let mut lfo = LFO::new(Waveform::Spline(points), 2.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/spline_2hz.png", "spline_2hz");
+    }
+
+    #[test]
+    fn spline_passes_through_control_points() {
+        let points = [(0.1, -0.5), (0.3, 0.9), (0.7, 0.1)];
+        for (time, value) in points {
+            assert!((spline(time, &points) - value).abs() < 1e-12);
+        }
+        let mut lfo = LFO::new(Waveform::Spline(points.to_vec()), 1.0, 1024.0);
+        assert!(((0..1024).map(|_| lfo.next()).nth(307).unwrap() - 0.95).abs() < 1e-3);
+        lfo.set_waveform(Waveform::Spline(vec![(0.0, 0.0)]));
+        assert_eq!(lfo.spline_tables[0].len(), SPLINE_TABLE_SIZE);
+        assert_eq!(lfo.next(), 0.5);
+    }
+
+    #[test]
+    fn custom_3hz() {
+        let mut lfo = LFO::new(Waveform::Custom(Box::new(|p| sine(p) * triangle(p))), 3.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/custom_3hz.png", "custom_3hz");
+    }
+
+    #[test]
+    fn custom_closure_keeps_state_and_lfo_is_send() {
+        fn assert_send<T: Send>(_: &T) {}
+        let mut calls = 0;
+        let mut lfo = LFO::new(Waveform::Custom(Box::new(move |_| {
+            calls += 1;
+            if calls % 2 == 0 { 1.0 } else { -1.0 }
+        })), 1.0, 1000.0);
+        assert_send(&lfo);
+        assert_eq!([lfo.next(), lfo.next(), lfo.next()], [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn bump_raised_cosine_3hz() {
+        let mut lfo = LFO::new(Waveform::Bump { width: 0.5, shape: BumpShape::RaisedCosine }, 3.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/bump_raised_cosine_3hz.png", "bump_raised_cosine_3hz");
+    }
+
+    #[test]
+    fn bump_gaussian_3hz() {
+        let mut lfo = LFO::new(Waveform::Bump { width: 0.5, shape: BumpShape::Gaussian }, 3.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/bump_gaussian_3hz.png", "bump_gaussian_3hz");
+    }
+
+    #[test]
+    fn bouncing_ball_1hz() {
+        let mut lfo = LFO::new(Waveform::BouncingBall { bounces: 5, damping: 0.6 }, 1.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/bouncing_ball_1hz.png", "bouncing_ball_1hz");
+    }
+
+    #[test]
+    fn bouncing_ball_arcs_shrink() {
+        // arcs of length 2/3 and 1/3, peaking at 1.0 and 0.25
+        assert!((bouncing_ball(1.0 / 3.0, 2, 0.5) - 1.0).abs() < 1e-12);
+        assert!((bouncing_ball(5.0 / 6.0, 2, 0.5) + 0.5).abs() < 1e-12);
+        assert_eq!(bouncing_ball(0.0, 2, 0.5), -1.0);
+    }
+
+    #[test]
+    fn skew_triangle_3hz() {
+        let mut lfo = LFO::new(Waveform::SkewTriangle(0.8), 3.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/skew_triangle_3hz.png", "skew_triangle_3hz");
+    }
+
+    #[test]
+    fn skew_triangle_spans_saws_and_triangle() {
+        for i in 0..100 {
+            let phase = i as f64 / 100.0;
+            assert!((skew_triangle(phase, 0.5) - triangle(phase)).abs() < 1e-12);
+            assert!((skew_triangle(phase, 1.0) - saw(phase, true)).abs() < 1e-12);
+            assert!((skew_triangle(phase, 0.0) - saw(phase, false)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn curved_saw_5hz() {
+        let mut lfo = LFO::new(Waveform::CurvedSaw { bend: 0.5, ramp_up: true }, 5.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/curved_saw_5hz.png", "curved_saw_5hz");
+    }
+
+    #[test]
+    fn curved_saw_bends_around_linear() {
+        assert_eq!(curved_saw(0.5, 0.0, true), 0.0);
+        assert!(curved_saw(0.5, 0.5, true) < 0.0);
+        assert!(curved_saw(0.5, -0.5, true) > 0.0);
+        assert_eq!(curved_saw(0.0, 0.7, false), 1.0);
+        assert!((curved_saw(1.0, -0.7, true) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn analog_sine_10hz() {
+        let mut lfo = LFO::new(Waveform::Sine, 10.0, 1000.0);
+        lfo.set_analog(1.0);
+        create_chart(&mut lfo, 1.0, "chart/analog_sine_10hz.png", "analog_sine_10hz");
+    }
+
+    #[test]
+    fn analog_drift_is_smooth_and_can_be_disabled() {
+        let mut clean = LFO::new(Waveform::Triangle, 10.0, 1000.0);
+        let mut analog = LFO::new(Waveform::Triangle, 10.0, 1000.0);
+        analog.set_analog(1.0);
+        let out: Vec<(f64, f64)> = (0..5000).map(|_| (clean.next(), analog.next())).collect();
+        assert!(out.iter().any(|(a, b)| (a - b).abs() > 1e-3));
+        assert!(out.windows(2).all(|w| (w[1].1 - w[0].1).abs() < 0.04));
+        assert!(analog.cycles.abs_diff(clean.cycles) <= 2);
+        analog.set_analog(0.0);
+        assert!(analog.analog.is_none());
+    }
+
+    #[test]
+    fn pattern_1hz() {
+        let mut lfo = LFO::new(Waveform::Pattern(0b10110100, 8), 1.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/pattern_1hz.png", "pattern_1hz");
+    }
+
+    #[test]
+    fn pattern_reads_bits_left_to_right() {
+        let gates: Vec<bool> = (0..8).map(|i| pattern((i as f64 + 0.5) / 8.0, 0b10110100, 8) > 0.0).collect();
+        assert_eq!(gates, [true, false, true, true, false, true, false, false]);
+        assert_eq!(pattern(0.9, 0b01, 2), 1.0);
+    }
+
+    #[test]
+    fn parabolic_5hz() {
+        let mut lfo = LFO::new(Waveform::Parabolic, 5.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/parabolic_5hz.png", "parabolic_5hz");
+    }
+
+    #[test]
+    fn parabolic_tracks_sine() {
+        for i in 0..100 {
+            let phase = i as f64 / 100.0;
+            assert!((parabolic(phase) - sine(phase)).abs() < 0.06);
+        }
+    }
+
+    #[test]
+    fn mix_sine_sample_hold_2hz() {
+        let waveform = Waveform::Mix(Box::new(Waveform::Sine), Box::new(Waveform::SampleHold(8)), 0.3);
+        let mut lfo = LFO::new(waveform, 2.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/mix_sine_sample_hold_2hz.png", "mix_sine_sample_hold_2hz");
+    }
+
+    #[test]
+    fn mix_crossfades_at_the_same_phase() {
+        let waveform = Waveform::Mix(Box::new(Waveform::SawUp), Box::new(Waveform::SawDn), 0.25);
+        let mut lfo = LFO::new(waveform, 1.0, 4.0);
+        let out: Vec<f64> = (0..4).map(|_| lfo.next()).collect();
+        assert_eq!(out, [0.25, 0.375, 0.5, 0.625]);
+    }
+
+    #[test]
+    fn composite_children_keep_their_own_state() {
+        let low = Box::new(Waveform::Spline(vec![(0.0, -1.0)]));
+        let high = Box::new(Waveform::Spline(vec![(0.0, 1.0)]));
+        let mut lfo = LFO::new(Waveform::Mix(low, high, 1.0), 1.0, 4.0);
+        // set up front, not on the first sample
+        assert_eq!(lfo.nested.len(), 2);
+        assert!(lfo.spline_tables[1..].iter().all(|table| table.len() == SPLINE_TABLE_SIZE));
+        assert_eq!(take(&mut lfo, 4), [1.0; 4]);
+
+        let held = || Box::new(Waveform::SampleHold(1));
+        let mut lfo = LFO::new(Waveform::Mix(held(), held(), 0.0), 1.0, 8.0);
+        let out = take(&mut lfo, 16);
+        assert!(out[..8].iter().all(|v| *v == out[0]));
+        assert!(out[8..].iter().all(|v| *v == out[8]));
+        assert_ne!(out[0], out[8]);
+
+        let waveforms = vec![Waveform::Spline(vec![(0.0, -1.0)]), Waveform::Spline(vec![(0.0, 1.0)])];
+        let transitions = vec![vec![0.0, 1.0], vec![1.0, 0.0]];
+        let mut lfo = LFO::new(Waveform::Chain(WaveformChain::new(waveforms, transitions)), 1.0, 4.0);
+        let firsts: Vec<f64> = (0..4).map(|_| take(&mut lfo, 4)[1]).collect();
+        assert_eq!(firsts, [0.0, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn chirp_1hz() {
+        let mut lfo = LFO::new(Waveform::Chirp { start: 2.0, end: 16.0 }, 1.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/chirp_1hz.png", "chirp_1hz");
+    }
+
+    #[test]
+    fn chirp_with_equal_ends_is_a_harmonic() {
+        for i in 0..100 {
+            let phase = i as f64 / 100.0;
+            assert!((chirp(phase, 3.0, 3.0) - sine(3.0 * phase)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn ease_out_bounce_2hz() {
+        let mut lfo = LFO::new(Waveform::Ease(Easing::OutBounce), 2.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/ease_out_bounce_2hz.png", "ease_out_bounce_2hz");
+    }
+
+    #[test]
+    fn easings_start_at_zero_and_end_at_one() {
+        let all = [
+            Easing::InQuad, Easing::OutQuad, Easing::InOutQuad,
+            Easing::InCubic, Easing::OutCubic, Easing::InOutCubic,
+            Easing::InOutSine, Easing::InExpo, Easing::OutExpo,
+            Easing::OutElastic, Easing::OutBounce,
+        ];
+        for easing in all {
+            assert!(ease(0.0, easing).abs() < 1e-9, "{:?}", easing);
+            assert!((ease(1.0, easing) - 1.0).abs() < 1e-9, "{:?}", easing);
+            assert!((eased(0.25, easing) - eased(0.75, easing)).abs() < 1e-9, "{:?}", easing);
+        }
+    }
+
+    #[test]
+    fn rc_3hz() {
+        let mut lfo = LFO::new(Waveform::RC { up: 0.05, down: 0.15 }, 3.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/rc_3hz.png", "rc_3hz");
+    }
+
+    #[test]
+    fn rc_approaches_rails() {
+        let mut lfo = LFO::new(Waveform::RC { up: 0.01, down: 0.01 }, 1.0, 1000.0);
+        let out: Vec<f64> = (0..1000).map(|_| lfo.next()).collect();
+        assert!(out[1] > out[0] && out[1] < 1.0);
+        assert!(out[499] > 0.999);
+        assert!(out[999] < 0.001);
+    }
 
-        let mut chart = ChartBuilder::on(&root)
-            .set_label_area_size(LabelAreaPosition::Left, 60)
-            .set_label_area_size(LabelAreaPosition::Bottom, 60)
-            .caption(cap, ("sans-serif", 40))
-            .build_cartesian_2d(-0.1_f64..t_sec+0.1, -1.1_f64..1.1_f64)
-            .unwrap();
+    #[test]
+    fn preset_heartbeat_1hz() {
+        let mut lfo = LFO::new(presets::heartbeat(), 1.0, 1000.0);
+        create_chart(&mut lfo, 2.0, "chart/preset_heartbeat_1hz.png", "preset_heartbeat_1hz");
+    }
 
-        chart
-            .configure_mesh()
-            //.disable_x_mesh()
-            //.disable_y_mesh()
-            .draw()
-            .unwrap();
+    #[test]
+    fn choose_2hz() {
+        let options = vec![(Waveform::Sine, 0.6), (Waveform::Triangle, 0.3), (Waveform::SampleHold(4), 0.1)];
+        let mut lfo = LFO::new(Waveform::Choose(options), 2.0, 1000.0);
+        create_chart(&mut lfo, 4.0, "chart/choose_2hz.png", "choose_2hz");
+    }
 
-        chart.draw_series(
-            AreaSeries::new(
-                (0..=data_len).zip(lfo_vec.iter()).map(|(x, y)| ((x as f64 / lfo.sample_rate, *y))),
-                0.0,
-                &RED.mix(0.2),
-            )
-            .border_style(&RED),
-        ).unwrap();
+    #[test]
+    fn choose_follows_weights() {
+        let options = vec![(Waveform::SawUp, 0.75), (Waveform::SawDn, 0.25), (Waveform::Sine, 0.0)];
+        let mut lfo = LFO::new(Waveform::Choose(options), 1.0, 4.0);
+        let mut counts = [0; 3];
+        for _ in 0..2000 {
+            let out: Vec<f64> = (0..4).map(|_| lfo.next()).collect();
+            let i = if out[1] == 0.25 { 0 } else if out[1] == 0.75 { 1 } else { 2 };
+            counts[i] += 1;
+        }
+        assert!((1350..1650).contains(&counts[0]), "{:?}", counts);
+        assert_eq!(counts[2], 0);
+    }
 
-        // To avoid the IO failure being ignored silently, we manually call the present function
-        root.present().unwrap();
+    #[test]
+    fn chain_2hz() {
+        let waveforms = vec![Waveform::Sine, Waveform::SawUp, Waveform::Pulse(0.3)];
+        let transitions = vec![vec![0.0, 1.0, 1.0], vec![1.0, 0.0, 0.0], vec![0.5, 0.5, 0.0]];
+        let mut lfo = LFO::new(Waveform::Chain(WaveformChain::new(waveforms, transitions)), 2.0, 1000.0);
+        create_chart(&mut lfo, 4.0, "chart/chain_2hz.png", "chain_2hz");
     }
 
     #[test]
-    fn sine_10hz() {
-        let mut lfo = LFO::new(Waveform::Sine, 10.0, 1000.0);
-        create_chart(&mut lfo, 1.0, "chart/sine_10hz.png", "sine_10hz");
+    fn chain_follows_transition_matrix() {
+        // a deterministic cycle 0 -> 2 -> 1 -> 0
+        let waveforms = vec![Waveform::SawUp, Waveform::SawDn, Waveform::Pulse(1.0)];
+        let transitions = vec![vec![0.0, 0.0, 1.0], vec![1.0, 0.0, 0.0], vec![0.0, 1.0, 0.0]];
+        let mut lfo = LFO::new(Waveform::Chain(WaveformChain::new(waveforms, transitions)), 1.0, 4.0);
+        let seconds: Vec<f64> = (0..6).map(|_| {
+            let cycle: Vec<f64> = (0..4).map(|_| lfo.next()).collect();
+            cycle[1]
+        }).collect();
+        assert_eq!(seconds, [0.25, 1.0, 0.75, 0.25, 1.0, 0.75]);
     }
 
     #[test]
-    fn triangle_3hz() {
-        let mut lfo = LFO::new(Waveform::Triangle, 3.0, 1000.0);
-        create_chart(&mut lfo, 1.0, "chart/triangle_3hz.png", "triangle_3hz");
+    fn wander_1hz() {
+        let mut lfo = LFO::new(Waveform::Wander { center: 0.3, stiffness: 2.0, noise: 0.8 }, 1.0, 1000.0);
+        create_chart(&mut lfo, 4.0, "chart/wander_1hz.png", "wander_1hz");
     }
 
     #[test]
-    fn sawup_5hz() {
-        let mut lfo = LFO::new(Waveform::SawUp, 5.0, 1000.0);
-        create_chart(&mut lfo, 1.0, "chart/sawup_5hz.png", "sawup_5hz");
+    fn wander_is_pulled_towards_center() {
+        let mut lfo = LFO::new(Waveform::Wander { center: 0.5, stiffness: 5.0, noise: 0.5 }, 2.0, 1000.0);
+        let out: Vec<f64> = (0..20_000).map(|_| 2.0 * lfo.next() - 1.0).collect();
+        let mean = out[2000..].iter().sum::<f64>() / 18_000.0;
+        assert!((mean - 0.5).abs() < 0.1, "{}", mean);
+        assert!(out.windows(2).any(|w| w[0] != w[1]));
     }
 
     #[test]
-    fn sawdn_5hz() {
-        let mut lfo = LFO::new(Waveform::SawDn, 5.0, 1000.0);
-        create_chart(&mut lfo, 1.0, "chart/sawdn_5hz.png", "sawdn_5hz");
+    fn gaussian_10hz() {
+        let waveform = Waveform::Gaussian { sigma: 0.3, interpolation: Interpolation::Cosine };
+        let mut lfo = LFO::new(waveform, 10.0, 1000.0);
+        create_chart(&mut lfo, 1.0, "chart/gaussian_10hz.png", "gaussian_10hz");
     }
 
     #[test]
-    fn pulse_25percent_5hz() {
-        let mut lfo = LFO::new(Waveform::Pulse(0.25), 5.0, 1000.0);
+    fn gaussian_draws_have_requested_spread() {
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 1000.0);
+        let draws: Vec<f64> = (0..20_000).map(|_| lfo.next_gaussian(0.2)).collect();
+        let mean = draws.iter().sum::<f64>() / draws.len() as f64;
+        let var = draws.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / draws.len() as f64;
+        assert!(mean.abs() < 0.01, "{}", mean);
+        assert!((var.sqrt() - 0.2).abs() < 0.01, "{}", var.sqrt());
+    }
+
+    #[test]
+    fn cycle_skip_sine_5hz() {
+        let mut lfo = LFO::new(Waveform::Sine, 5.0, 1000.0);
+        lfo.set_cycle_skip(0.4, SkipMode::Center);
+        create_chart(&mut lfo, 2.0, "chart/cycle_skip_sine_5hz.png", "cycle_skip_sine_5hz");
+    }
+
+    #[test]
+    fn cycle_skip_holds_whole_cycles() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.set_cycle_skip(0.5, SkipMode::Hold);
+        let cycles: Vec<Vec<f64>> = (0..200).map(|_| (0..4).map(|_| lfo.next()).collect()).collect();
+        let skipped = cycles.iter().filter(|c| c.iter().all(|v| *v == c[0])).count();
+        assert!((60..140).contains(&skipped), "{}", skipped);
+        assert!(cycles.iter().all(|c| c == &[0.0, 0.25, 0.5, 0.75] || c.iter().all(|v| *v == 0.75)));
+    }
+
+    #[test]
+    fn ratchet_saw_2hz() {
+        let mut lfo = LFO::new(Waveform::SawDn, 2.0, 1000.0);
+        lfo.ratchet(4);
+        create_chart(&mut lfo, 2.0, "chart/ratchet_saw_2hz.png", "ratchet_saw_2hz");
+    }
+
+    #[test]
+    fn ratchet_subdivides_only_the_next_cycle() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.ratchet(2);
+        let out: Vec<f64> = (0..12).map(|_| lfo.next()).collect();
+        assert_eq!(out, [0.0, 0.25, 0.5, 0.75, 0.0, 0.5, 0.0, 0.5, 0.0, 0.25, 0.5, 0.75]);
+    }
+
+    #[test]
+    fn set_freq_does_not_jump_phase() {
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 1000.0);
+        let mut out: Vec<f64> = (0..700).map(|_| lfo.next()).collect();
+        lfo.set_freq(3.0);
+        out.extend((0..700).map(|_| lfo.next()));
+        // the steepest slope of the unipolar 3 Hz sine is 0.5 * tau * 3 / 1000 per sample
+        assert!(out.windows(2).all(|w| (w[1] - w[0]).abs() < 0.0095));
+    }
+
+    #[test]
+    fn set_sample_rate_preserves_phase() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 100.0);
+        let before: Vec<f64> = (0..25).map(|_| lfo.next()).collect();
+        lfo.set_sample_rate(1000.0);
+        let after = lfo.next();
+        assert!((before[24] - 0.24).abs() < 1e-9);
+        assert!((after - 0.25).abs() < 1e-9);
+        assert!((lfo.next() - 0.251).abs() < 1e-9);
+    }
+
+    #[test]
+    fn set_phase_jumps_without_counting_a_cycle() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.next();
+        assert_eq!(lfo.phase(), 0.25);
+        lfo.next();
+        lfo.set_phase(1.25);
+        assert_eq!(lfo.phase(), 0.25);
+        assert_eq!(lfo.next(), 0.25);
+        assert_eq!(lfo.cycles, 0);
+        lfo.set_phase(-0.25);
+        assert_eq!(lfo.next(), 0.75);
+    }
+
+    #[test]
+    fn phase_offsets_are_normalized() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.set_phase_degrees(450.0);
+        assert_eq!(lfo.theta, 0.25);
+        lfo.set_phase_degrees(-90.0);
+        assert_eq!(lfo.theta, 0.75);
+        assert_eq!(lfo.next(), 0.75);
+        lfo.set_phase_cycles(2.5);
+        assert_eq!(lfo.theta, 0.5);
+        lfo.set_theta(-1.25);
+        assert_eq!(lfo.theta, 0.75);
+    }
+
+    #[test]
+    fn trigger_modes() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        take(&mut lfo, 2);
+        lfo.trigger();
+        assert_eq!(take(&mut lfo, 2), [0.5, 0.75]);
+        lfo.set_trigger_mode(TriggerMode::Retrigger);
+        lfo.trigger();
+        assert_eq!(take(&mut lfo, 6), [0.0, 0.25, 0.5, 0.75, 0.0, 0.25]);
+        lfo.set_trigger_mode(TriggerMode::OneShot);
+        lfo.trigger();
+        assert_eq!(take(&mut lfo, 4), [0.0, 0.25, 0.5, 0.75]);
+        lfo.trigger();
+        assert_eq!(take(&mut lfo, 2), [0.0, 0.25]);
+    }
+
+    #[test]
+    fn one_shot_holds_end_value() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.set_trigger_mode(TriggerMode::OneShot);
+        lfo.trigger();
+        assert_eq!(take(&mut lfo, 4), [0.0, 0.25, 0.5, 0.75]);
+        assert!(!lfo.is_finished());
+        let held = take(&mut lfo, 3);
+        assert!(lfo.is_finished());
+        assert!(held.iter().all(|v| (v - 1.0).abs() < 1e-9));
+        lfo.trigger();
+        assert!(!lfo.is_finished());
+        assert_eq!(lfo.next(), 0.0);
+        lfo.set_trigger_mode(TriggerMode::Free);
+        assert!(!lfo.is_finished());
+    }
+
+    #[test]
+    fn loop_count_then_hold() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.set_loop_count(Some(3));
+        let out = take(&mut lfo, 16);
+        assert_eq!(out[..12], [0.0, 0.25, 0.5, 0.75].repeat(3));
+        assert!(out[12..].iter().all(|v| (v - 1.0).abs() < 1e-9));
+        assert!(lfo.is_finished());
+        assert_eq!(lfo.cycles, 2);
+
+        lfo.set_trigger_mode(TriggerMode::Retrigger);
+        lfo.trigger();
+        assert!(!lfo.is_finished());
+        assert!((take(&mut lfo, 13)[12] - 1.0).abs() < 1e-9);
+        assert!(lfo.is_finished());
+        lfo.set_loop_count(None);
+        lfo.reset();
+        take(&mut lfo, 20);
+        assert!(!lfo.is_finished());
+    }
+
+    #[test]
+    fn start_delay_idles_after_reset() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.set_start_delay(0.75, 1.0);
+        assert_eq!(take(&mut lfo, 2), [0.0, 0.25]);
+        lfo.reset();
+        assert_eq!(take(&mut lfo, 5), [1.0, 1.0, 1.0, 0.0, 0.25]);
+        lfo.set_start_delay(0.0, 0.0);
+        lfo.reset();
+        assert_eq!(lfo.next(), 0.0);
+    }
+
+    #[test]
+    fn fade_in_ramps_the_depth() {
+        let mut lfo = LFO::new(Waveform::Pulse(0.5), 2.0, 8.0);
+        lfo.set_fade_in(0.5);
+        lfo.reset();
+        assert_eq!(take(&mut lfo, 6), [0.5, 0.625, 0.25, 0.125, 1.0, 1.0]);
+        lfo.set_start_delay(0.25, 0.0);
+        lfo.reset();
+        assert_eq!(take(&mut lfo, 4), [0.5, 0.5, 0.5, 0.625]);
+    }
+
+    #[test]
+    fn decay_dies_away() {
+        let mut lfo = LFO::new(Waveform::Pulse(0.5), 2.0, 8.0);
+        lfo.set_decay(0.5, DecayCurve::Linear);
+        assert_eq!(take(&mut lfo, 6), [1.0, 0.875, 0.25, 0.375, 0.5, 0.5]);
+        lfo.set_decay(1.0, DecayCurve::Exponential);
+        lfo.reset();
+        let out = take(&mut lfo, 9);
+        assert!((out[8] - 0.5 * (1.0 + (-1.0f64).exp())).abs() < 1e-9);
+        lfo.set_decay(0.0, DecayCurve::Linear);
+        assert_eq!(take(&mut lfo, 2), [1.0, 0.0]);
+    }
+
+    #[test]
+    fn cycle_completion_event() {
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 4.0);
+        let events: Vec<bool> = (0..9).map(|_| {
+            lfo.next();
+            lfo.cycle_completed()
+        }).collect();
+        assert_eq!(events, [false, false, false, false, true, false, false, false, true]);
+        assert_eq!(lfo.cycles(), 2);
+
+        lfo.set_trigger_mode(TriggerMode::OneShot);
+        take(&mut lfo, 4);
+        assert!(lfo.cycle_completed());
+        lfo.next();
+        assert!(!lfo.cycle_completed());
+    }
+
+    #[test]
+    fn threshold_trigger_with_hysteresis() {
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 100.0);
+        lfo.set_threshold_trigger(0.5, 0.1, Edge::Rising);
+        let mut fired = Vec::new();
+        for i in 0..300 {
+            lfo.next();
+            if lfo.threshold_crossed() {
+                fired.push(i);
+            }
+        }
+        // up through 0.55 once per cycle, the 0.5 the sine starts on not counting
+        assert_eq!(fired, [2, 102, 202]);
+
+        let noisy = [0.4, 0.52, 0.48, 0.56, 0.47, 0.53, 0.44, 0.6];
+        let mut lfo = LFO::new(Waveform::Custom(Box::new(move |p| 2.0 * noisy[(p * 8.0) as usize] - 1.0)), 1.0, 8.0);
+        lfo.set_threshold_trigger(0.5, 0.1, Edge::Both);
+        let crossings: Vec<(bool, bool)> = (0..8).map(|_| {
+            lfo.next();
+            (lfo.threshold_crossed(), lfo.above_threshold())
+        }).collect();
+        assert_eq!(crossings.iter().filter(|c| c.0).count(), 3);
+        assert!(crossings[3].1 && crossings[5].1 && !crossings[6].1 && crossings[7].1);
+    }
+
+    #[test]
+    fn companion_gate_and_sync() {
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 4.0);
+        let gate: Vec<f64> = (0..6).map(|_| lfo.next_with(Companion::Gate).1).collect();
+        assert_eq!(gate, [1.0, 1.0, 0.0, 0.0, 1.0, 1.0]);
+        let sync: Vec<f64> = (0..6).map(|_| lfo.next_with(Companion::Sync).1).collect();
+        assert_eq!(sync, [0.0, 0.0, 1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(lfo.next_with(Companion::Gate).0, 0.5);
+
+        lfo.reset();
+        let sync: Vec<f64> = (0..5).map(|_| lfo.next_with(Companion::Sync).1).collect();
+        assert_eq!(sync, [1.0, 0.0, 0.0, 0.0, 1.0]);
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 4.0);
+        lfo.set_trigger_mode(TriggerMode::Retrigger);
+        take(&mut lfo, 2);
+        lfo.trigger();
+        assert_eq!(lfo.next_with(Companion::Sync).1, 1.0);
+    }
+
+    #[test]
+    fn scheduled_reset_is_sample_accurate() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 8.0);
+        take(&mut lfo, 3);
+        lfo.reset_at(2);
+        assert_eq!(take(&mut lfo, 4), [0.375, 0.5, 0.0, 0.125]);
+        lfo.reset_at(0);
+        assert_eq!(lfo.next(), 0.0);
+        lfo.reset_at(5);
+        lfo.reset();
+        assert_eq!(take(&mut lfo, 8), [0.0, 0.125, 0.25, 0.375, 0.5, 0.625, 0.75, 0.875]);
+    }
+
+    #[test]
+    fn swing_delays_the_second_half() {
+        let mut lfo = LFO::new(Waveform::Pulse(0.5), 1.0, 8.0);
+        lfo.set_swing(0.5);
+        assert_eq!(take(&mut lfo, 8), [1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0]);
+        lfo.ratchet(2);
+        assert_eq!(take(&mut lfo, 8), [1.0, 1.0, 1.0, 0.0, 1.0, 1.0, 1.0, 0.0]);
+        assert_eq!(swing(0.0, 0.3), 0.0);
+        assert!((swing(0.65, 0.3) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn phase_distortion_brightens_a_sine() {
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 8.0);
+        lfo.set_output_mode(OutputMode::Bipolar);
+        lfo.set_phase_distortion(0.5);
+        let out = take(&mut lfo, 8);
+        assert!((out[1] - 1.0).abs() < 1e-12 && out[2].abs() < 1e-12, "{:?}", out);
+        assert!((out[5] + 1.0).abs() < 1e-12);
+        assert!((phase_distortion(0.75, 0.5) - 5.0 / 6.0).abs() < 1e-12);
+        lfo.set_phase_distortion(0.0);
+        assert!((take(&mut lfo, 2)[1] - 0.5f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn through_zero_fm_plays_smoothly_backwards() {
+        // 1Hz swept by +-3Hz spends part of each modulator cycle with a negative rate
+        let modulator = LFO::new(Waveform::Sine, 0.5, 1000.0);
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 1000.0);
+        lfo.set_freq_mod(modulator.map(|v| 2.0 * v - 1.0), 3.0);
+        let out = take(&mut lfo, 4000);
+        assert!(out.windows(2).all(|w| (w[1] - w[0]).abs() < 0.5 * TAU * 4.0 / 1000.0));
+
+        let modulator = signal::from_iter([0.0, 0.0, -2.0, -2.0, -2.0]);
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.set_freq_mod(modulator, 1.0);
+        assert_eq!(take(&mut lfo, 6), [0.0, 0.25, 0.5, 0.25, 0.0, 0.75]);
+        assert_eq!(lfo.cycles, 1);
+        assert_eq!(lfo.next(), 0.0);
+        assert_eq!(lfo.cycles, 2);
+    }
+
+    #[test]
+    fn phase_warp_presets() {
+        for warp in [PhaseWarp::Exponential(0.7), PhaseWarp::Exponential(-0.7), PhaseWarp::Sigmoid(0.5)] {
+            let out: Vec<f64> = (0..=100).map(|i| time_warp(i as f64 / 100.0, warp)).collect();
+            assert_eq!(out[0], 0.0);
+            assert!((out[100] - 1.0).abs() < 1e-9);
+            assert!(out.windows(2).all(|w| w[1] >= w[0]), "{:?}", warp);
+        }
+        assert!(time_warp(0.25, PhaseWarp::Exponential(0.7)) < 0.25);
+        assert!(time_warp(0.25, PhaseWarp::Exponential(-0.7)) > 0.25);
+        assert!(time_warp(0.25, PhaseWarp::Sigmoid(0.5)) < 0.25);
+        assert!((time_warp(0.5, PhaseWarp::Sigmoid(0.5)) - 0.5).abs() < 1e-12);
+
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.set_phase_warp(Some(PhaseWarp::Custom(|p| p * p)));
+        assert_eq!(take(&mut lfo, 4), [0.0, 0.0625, 0.25, 0.5625]);
+        lfo.set_phase_warp(Some(PhaseWarp::Custom(|p| 2.0 * p)));
+        assert_eq!(take(&mut lfo, 4)[3], 1.0 - f64::EPSILON);
+        lfo.set_phase_warp(None);
+        assert_eq!(take(&mut lfo, 2), [0.0, 0.25]);
+    }
+
+    #[test]
+    fn long_runs_keep_time() {
+        // a little over 20 seconds at 48kHz at a rate that never lands on a sample
+        let n = 1_000_003u64;
+        let mut lfo = LFO::new(Waveform::Sine, 1.37, 48000.0);
+        for _ in 0..n {
+            lfo.next();
+        }
+        let exact = n as f64 * 1.37 / 48000.0;
+        assert_eq!(lfo.cycles(), exact.floor() as u64);
+        assert!((lfo.phase() - exact.fract()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fractional_sample_rates() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 2.5);
+        let out = take(&mut lfo, 6);
+        for (v, expected) in out.iter().zip([0.0, 0.4, 0.8, 0.2, 0.6, 0.0]) {
+            assert!((v - expected).abs() < 1e-12);
+        }
+        assert_eq!(lfo.cycles(), 2);
+
+        // varispeed: at 44100.5, 1Hz wraps on sample 44101 rather than 44100
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 44100.5);
+        take(&mut lfo, 44101);
+        assert_eq!(lfo.cycles(), 0);
+        take(&mut lfo, 1);
+        assert_eq!(lfo.cycles(), 1);
+        assert!((lfo.phase() - 1.5 / 44100.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn plateau_holds_at_the_extremes() {
+        let holds = [(0.25, 0.2), (0.75, 0.2)];
+        assert_eq!(plateau(0.0, &holds), 0.0);
+        assert_eq!(plateau(0.2, &holds), 0.25);
+        assert_eq!(plateau(0.35, &holds), 0.25);
+        assert!((plateau(0.5, &holds) - 0.5).abs() < 1e-12);
+        assert_eq!(plateau(0.7, &holds), 0.75);
+
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 100.0);
+        lfo.set_plateau(0.2, 0.1);
+        let out = take(&mut lfo, 100);
+        assert_eq!(out.iter().filter(|v| **v == 1.0).count(), 20);
+        assert_eq!(out.iter().filter(|v| **v == 0.0).count(), 10);
+        assert_eq!(lfo.cycles(), 0);
+        lfo.set_waveform(Waveform::SawDn);
+        assert_eq!(lfo.extremes, (0.0, 1023.0 / 1024.0));
+        lfo.set_plateau(0.0, 0.0);
+        let out = take(&mut lfo, 2);
+        assert!((out[0] - 1.0).abs() < 1e-9 && (out[1] - 0.99).abs() < 1e-9);
+    }
+
+    #[test]
+    fn loop_points_repeat_a_sub_cycle() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 8.0);
+        lfo.set_loop_points(Some((0.25, 0.75)));
+        assert_eq!(take(&mut lfo, 10), [0.0, 0.125, 0.25, 0.375, 0.5, 0.625, 0.25, 0.375, 0.5, 0.625]);
+        assert_eq!(lfo.cycles(), 1);
+        lfo.reset();
+        assert_eq!(take(&mut lfo, 2), [0.0, 0.125]);
+
+        lfo.set_freq(-1.0);
+        lfo.set_phase(0.5);
+        assert_eq!(take(&mut lfo, 4), [0.5, 0.375, 0.75, 0.625]);
+
+        lfo.set_loop_points(Some((0.5, 0.5)));
+        assert_eq!(lfo.loop_points, None);
+    }
+
+    #[test]
+    fn hard_sync_3hz_to_2hz() {
+        let clock = LFO::new(Waveform::Pulse(0.1), 2.0, 1000.0);
+        let mut lfo = LFO::new(Waveform::SawUp, 3.0, 1000.0).hard_sync(clock);
+        chart_signal(&mut lfo, 1000.0, 2.0, "chart/hard_sync_3hz_to_2hz.png", "hard_sync_3hz_to_2hz");
+        let clock = LFO::new(Waveform::Pulse(0.1), 2.0, 1000.0);
+        let mut lfo = LFO::new(Waveform::SawUp, 3.0, 1000.0).hard_sync(clock);
+        let out: Vec<f64> = (0..=1000).map(|_| lfo.next()).collect();
+        assert_eq!(out[0], 0.0);
+        assert_eq!(out[500], 0.0);
+        assert!(out[499] > 0.0);
+    }
+
+    #[test]
+    fn soft_sync_reverses_direction() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        take(&mut lfo, 3);
+        lfo.reverse();
+        assert_eq!(take(&mut lfo, 2), [0.25, 0.0]);
+        assert_eq!(lfo.cycles, 0);
+        assert_eq!(take(&mut lfo, 2), [0.75, 0.5]);
+        assert_eq!(lfo.cycles, 1);
+        lfo.reset();
+        assert_eq!(take(&mut lfo, 2), [0.0, 0.25]);
+
+        // a 2Hz gate against a 3Hz saw folds it back every half second
+        let clock = LFO::new(Waveform::Pulse(0.1), 2.0, 1000.0);
+        let mut lfo = LFO::new(Waveform::SawUp, 3.0, 1000.0).soft_sync(clock);
+        let out: Vec<f64> = (0..1000).map(|_| lfo.next()).collect();
+        assert!(out.windows(2).all(|w| (w[1] - w[0]).abs() < 0.01 || (w[1] - w[0]).abs() > 0.99));
+        assert!(out[499] < out[498] && out[501] > out[500]);
+    }
+
+    #[test]
+    fn quadrature_is_a_quarter_cycle_ahead() {
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 8.0);
+        for i in 0..8 {
+            let (sin, cos) = lfo.next_quadrature();
+            let p = i as f64 / 8.0;
+            assert!((sin - 0.5 * (sine(p) + 1.0)).abs() < 1e-12);
+            assert!((cos - 0.5 * ((TAU * p).cos() + 1.0)).abs() < 1e-12);
+        }
+
+        // taps leave random shapes drawing the same sequence
+        let mut plain = LFO::new(Waveform::SampleHold(4), 1.0, 100.0);
+        let mut tapped = LFO::new(Waveform::SampleHold(4), 1.0, 100.0);
+        let expected = take(&mut plain, 300);
+        assert!(expected.iter().all(|v| *v == tapped.next_quadrature().0));
+    }
+
+    #[test]
+    fn tap_lags_the_main_output() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 8.0);
+        take(&mut lfo, 3);
+        assert_eq!(lfo.next_with_tap(0.25), (0.375, 0.125));
+        assert_eq!(lfo.next_with_tap(-1.5), (0.5, 0.0));
+        assert_eq!(lfo.next(), 0.625);
+    }
+
+    #[test]
+    fn ensemble_voices_drift_apart() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 8.0);
+        lfo.set_ensemble(3, 0.5, 0.0);
+        let mut out = [0.0; 3];
+        lfo.next_ensemble(&mut out);
+        assert_eq!(out, [0.0, 0.0, 0.0]);
+        lfo.next_ensemble(&mut out);
+        assert_eq!(out, [0.0625, 0.125, 0.1875]);
+        assert_eq!(lfo.next_ensemble_mix(), 0.25);
+
+        lfo.set_ensemble(4, 0.0, 1.0);
+        lfo.reset();
+        let mut out = [0.0; 4];
+        lfo.next_ensemble(&mut out);
+        assert_eq!(out, [0.0, 0.25, 0.5, 0.75]);
+
+        lfo.set_ensemble(1, 0.5, 0.5);
+        assert_eq!(lfo.next_ensemble_mix(), 0.125);
+    }
+
+    #[test]
+    fn n_phases_are_evenly_spread() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 8.0);
+        let mut out = [0.0; 4];
+        lfo.next_n_phases(&mut out);
+        assert_eq!(out, [0.0, 0.25, 0.5, 0.75]);
+        lfo.next_n_phases(&mut out);
+        assert_eq!(out, [0.125, 0.375, 0.625, 0.875]);
+        lfo.next_n_phases(&mut []);
+        assert_eq!(lfo.next(), 0.375);
+    }
+
+    #[test]
+    fn phase_mod_shifts_the_lookup() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.set_phase_mod(signal::from_iter([0.0, 1.0, -1.0, 2.0]), 0.25);
+        assert_eq!(take(&mut lfo, 4), [0.0, 0.5, 0.25, 0.25]);
+        assert_eq!(lfo.cycles, 0);
+        lfo.clear_phase_mod();
+        assert_eq!(take(&mut lfo, 2), [0.0, 0.25]);
+        assert_eq!(lfo.cycles, 1);
+    }
+
+    #[test]
+    fn freq_mod_bends_the_rate_without_jumps() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 8.0);
+        lfo.set_freq_mod(signal::from_iter([1.0, 1.0, -1.0, -1.0]), 1.0);
+        assert_eq!(take(&mut lfo, 5), [0.0, 0.25, 0.5, 0.5, 0.5]);
+        lfo.clear_freq_mod();
+        assert_eq!(take(&mut lfo, 2), [0.625, 0.75]);
+
+        let modulator = LFO::new(Waveform::Sine, 2.0, 1000.0);
+        let mut lfo = LFO::new(Waveform::Sine, 5.0, 1000.0);
+        lfo.set_freq_mod(modulator, 3.0);
+        let out = take(&mut lfo, 2000);
+        assert!(out.windows(2).all(|w| (w[1] - w[0]).abs() < 0.5 * TAU * 8.0 / 1000.0));
+    }
+
+    #[test]
+    fn duty_mod_sweeps_the_pulse_width() {
+        let mut lfo = LFO::new(Waveform::Pulse(0.5), 1.0, 4.0);
+        lfo.set_duty_mod(signal::from_iter([0.0, -1.0, 1.0, 1.0, 4.0]), 0.25);
+        assert_eq!(take(&mut lfo, 5), [1.0, 0.0, 1.0, 0.0, 1.0]);
+
+        // an internal LFO as the modulator
+        let mut lfo = LFO::new(Waveform::Pulse(0.5), 10.0, 1000.0);
+        lfo.set_duty_mod(LFO::new(Waveform::SawUp, 1.0, 1000.0), -0.4);
+        let out = take(&mut lfo, 1000);
+        let width = |cycle: &[f64]| cycle.iter().filter(|v| **v > 0.5).count();
+        assert!((45..=50).contains(&width(&out[..100])));
+        assert!(width(&out[900..]) < 15);
+    }
+
+    #[test]
+    fn random_start_on_reset() {
+        let starts = |seed: u64| {
+            let mut lfo = LFO::new(Waveform::SawUp, 1.0, 100.0);
+            lfo.set_seed(seed);
+            lfo.set_random_start(true);
+            (0..4).map(|_| {
+                lfo.reset();
+                lfo.phase()
+            }).collect::<Vec<f64>>()
+        };
+        let a = starts(1);
+        assert_eq!(a, starts(1));
+        assert_ne!(a, starts(2));
+        assert!(a.iter().all(|p| (0.0..1.0).contains(p)));
+        assert!(a.windows(2).all(|w| w[0] != w[1]));
+
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 100.0);
+        lfo.set_random_start(true);
+        lfo.set_random_start(false);
+        lfo.reset();
+        assert_eq!(lfo.phase(), 0.0);
+    }
+
+    #[test]
+    fn negative_freq_plays_backwards() {
+        let mut lfo = LFO::new(Waveform::SawUp, -1.0, 4.0);
+        assert_eq!(take(&mut lfo, 6), [0.0, 0.75, 0.5, 0.25, 0.0, 0.75]);
+        assert_eq!(lfo.cycles, 2);
+
+        // one draw per step, in either direction
+        let mut lfo = LFO::new(Waveform::SampleHold(4), -1.0, 100.0);
+        let out = take(&mut lfo, 200);
+        assert_eq!(out.windows(2).filter(|w| w[0] != w[1]).count(), 8);
+
+        let mut lfo = LFO::new(Waveform::SawUp, -1.0, 4.0);
+        lfo.set_phase(0.5);
+        lfo.set_trigger_mode(TriggerMode::OneShot);
+        assert_eq!(take(&mut lfo, 4), [0.5, 0.25, 0.0, 0.0]);
+        assert!(lfo.is_finished());
+    }
+
+    #[test]
+    fn freeze_holds_and_resumes() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 8.0);
+        take(&mut lfo, 3);
+        lfo.freeze();
+        assert!(lfo.is_frozen());
+        assert_eq!(take(&mut lfo, 3), [0.25, 0.25, 0.25]);
+        lfo.unfreeze();
+        assert_eq!(take(&mut lfo, 2), [0.375, 0.5]);
+    }
+
+    #[test]
+    fn duty_sweep_once_and_ping_pong() {
+        let width = |lfo: &mut LFO| take(lfo, 100).iter().filter(|v| **v > 0.5).count();
+        let mut lfo = LFO::new(Waveform::Pulse(0.5), 10.0, 1000.0);
+        lfo.set_duty_sweep(0.1, 0.9, 1.0, SweepMode::Once);
+        let widths: Vec<usize> = (0..12).map(|_| width(&mut lfo)).collect();
+        assert!(widths[0] < 20 && widths[9] > 80);
+        assert_eq!(widths[10], 90);
+        assert_eq!(widths[11], 90);
+
+        lfo.set_duty_sweep(0.1, 0.9, 0.5, SweepMode::PingPong);
+        let widths: Vec<usize> = (0..10).map(|_| width(&mut lfo)).collect();
+        assert!(widths[4] > 70 && widths[9] < 30);
+        lfo.reset();
+        assert!(width(&mut lfo) < 20);
+        lfo.clear_duty_sweep();
+        assert_eq!(width(&mut lfo), 50);
+    }
+
+    #[test]
+    fn tempo_sync_sets_the_rate() {
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 1000.0);
+        lfo.set_rate_division(NoteValue::Eighth);
+        assert_eq!(lfo.freq, 1.0);
+        lfo.set_tempo(90.0);
+        assert_eq!(lfo.freq, 3.0);
+        lfo.set_rate_division(NoteValue::Whole);
+        assert_eq!(lfo.freq, 0.375);
+        lfo.set_rate_division(NoteValue::Quarter.dotted());
+        assert_eq!(lfo.freq, 1.0);
+        lfo.set_freq(5.0);
+        lfo.set_rate_division(NoteValue::Quarter);
+        assert_eq!(lfo.freq, 5.0);
+    }
+
+    #[test]
+    fn beat_error_takes_the_short_way_round() {
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 1000.0);
+        lfo.set_rate_division(NoteValue::Half);
+        lfo.set_phase(0.9);
+        assert!((lfo.beat_error(4.1) - 0.15).abs() < 1e-9);
+        lfo.set_phase(0.1);
+        assert!((lfo.beat_error(1.9) + 0.15).abs() < 1e-9);
+        lfo.nudge_phase(lfo.beat_error(1.9));
+        assert!(lfo.beat_error(1.9).abs() < 1e-9);
+    }
+
+    #[test]
+    fn shapers_after_gain() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.set_output_mode(OutputMode::Bipolar);
+        lfo.set_shaper(Shaper::Clip, 4.0);
+        assert_eq!(take(&mut lfo, 4), [-1.0, -1.0, 0.0, 1.0]);
+        lfo.set_shaper(Shaper::Fold, 3.0);
+        assert_eq!(take(&mut lfo, 4), [1.0, -0.5, 0.0, 0.5]);
+        lfo.set_shaper(Shaper::Tanh, 2.0);
         lfo.set_gain(0.5);
-        create_chart(&mut lfo, 1.0, "chart/pulse_25percent_2hz.png", "pulse_25percent_2hz");
+        let out = take(&mut lfo, 4);
+        assert!((out[0] + 1.0f64.tanh() / 2.0f64.tanh()).abs() < 1e-12);
+        lfo.clear_shaper();
+        assert_eq!(take(&mut lfo, 1), [-0.5]);
+    }
+
+    #[test]
+    fn dead_zone_and_hysteresis() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 8.0);
+        lfo.set_output_mode(OutputMode::Bipolar);
+        lfo.set_dead_zone(0.3);
+        assert_eq!(take(&mut lfo, 8), [-1.0, -0.75, -0.5, 0.0, 0.0, 0.0, 0.5, 0.75]);
+        lfo.set_dead_zone(0.0);
+        lfo.set_hysteresis(0.3);
+        assert_eq!(take(&mut lfo, 8), [-1.0, -1.0, -0.5, -0.5, 0.0, 0.0, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn output_modes() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.set_gain(0.5);
+        lfo.set_output_mode(OutputMode::Bipolar);
+        assert_eq!(take(&mut lfo, 4), [-0.5, -0.25, 0.0, 0.25]);
+        lfo.set_output_mode(OutputMode::Unipolar);
+        assert_eq!(take(&mut lfo, 4), [0.0, 0.125, 0.25, 0.375]);
+        lfo.set_output_mode(OutputMode::InvertedUnipolar);
+        assert_eq!(take(&mut lfo, 4), [0.5, 0.375, 0.25, 0.125]);
+    }
+
+    #[test]
+    fn steps_quantize_any_shape() {
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 8.0);
+        lfo.set_steps(Some(3));
+        let out = take(&mut lfo, 8);
+        assert!(out.iter().all(|v| [0.0, 0.5, 1.0].contains(v)), "{:?}", out);
+        assert_eq!(out[2], 1.0);
+        assert_eq!(out[6], 0.0);
+        lfo.set_steps(Some(0));
+        assert!(take(&mut lfo, 8).iter().all(|v| *v == 0.0 || *v == 1.0));
+        lfo.set_steps(None);
+        assert!((take(&mut lfo, 2)[1] - (0.5 + 0.5 * 0.5f64.sqrt())).abs() < 1e-12);
+    }
+
+    #[test]
+    fn slew_limits_rise_and_fall_separately() {
+        let mut lfo = LFO::new(Waveform::Pulse(0.5), 1.0, 100.0);
+        lfo.set_phase(0.5);
+        lfo.set_slew(10.0, f64::INFINITY);
+        assert_eq!(lfo.next(), 0.0);
+        take(&mut lfo, 49);
+        let rising = take(&mut lfo, 12);
+        assert!((rising[4] - 0.5).abs() < 1e-9 && rising[11] == 1.0, "{:?}", rising);
+        take(&mut lfo, 38);
+        assert_eq!(lfo.next(), 0.0);
+        lfo.clear_slew();
+        lfo.set_slew(f64::INFINITY, 20.0);
+        take(&mut lfo, 99);
+        let falling = take(&mut lfo, 3);
+        assert!((falling[0] - 0.8).abs() < 1e-9 && (falling[2] - 0.4).abs() < 1e-9, "{:?}", falling);
+    }
+
+    #[test]
+    fn smoothing_is_a_one_pole_lowpass() {
+        let mut lfo = LFO::new(Waveform::Pulse(0.5), 1.0, 1000.0);
+        lfo.set_phase(0.5);
+        lfo.set_smoothing(0.01);
+        take(&mut lfo, 500);
+        let out = take(&mut lfo, 10);
+        assert!((out[9] - (1.0 - (-1.0f64).exp())).abs() < 1e-9, "{:?}", out);
+        lfo.set_smoothing(0.0);
+        assert_eq!(lfo.next(), 1.0);
+    }
+
+    #[test]
+    fn scale_quantizes_semitones() {
+        let mut lfo = LFO::new(Waveform::Triangle, 1.0, 100.0);
+        lfo.set_output_range(0.0, 12.0);
+        lfo.set_scale(Some(Scale::minor_pentatonic(9)));
+        let notes = take(&mut lfo, 100);
+        assert!(notes.iter().all(|n| [0.0, 2.0, 4.0, 7.0, 9.0, 12.0].contains(n)), "{:?}", notes);
+        assert!(notes.contains(&4.0) && notes.contains(&12.0));
+    }
+
+    #[test]
+    fn dithered_steps_average_out() {
+        let mut lfo = LFO::new(Waveform::Custom(Box::new(|_| 0.3)), 1.0, 1000.0);
+        lfo.set_output_mode(OutputMode::Bipolar);
+        lfo.set_steps(Some(3));
+        assert!(take(&mut lfo, 10).iter().all(|v| *v == 0.0));
+        lfo.set_dither(Some(Dither::ErrorFeedback));
+        let out = take(&mut lfo, 1000);
+        assert!(out.iter().all(|v| *v == 0.0 || *v == 1.0));
+        assert!((out.iter().sum::<f64>() / 1000.0 - 0.3).abs() < 0.002);
+        lfo.set_dither(Some(Dither::Tpdf));
+        let out = take(&mut lfo, 20000);
+        assert!(out.iter().all(|v| [-1.0, 0.0, 1.0].contains(v)));
+        assert!((out.iter().sum::<f64>() / 20000.0 - 0.3).abs() < 0.03);
+    }
+
+    #[test]
+    fn offset_and_clamp() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.set_output_mode(OutputMode::Bipolar);
+        lfo.set_gain(0.2);
+        lfo.set_offset(0.9);
+        let out = take(&mut lfo, 4);
+        assert!((out[0] - 0.7).abs() < 1e-12 && (out[3] - 1.0).abs() < 1e-12);
+        lfo.set_clamp(Some((0.0, 0.95)));
+        assert_eq!(take(&mut lfo, 4)[3], 0.95);
+    }
+
+    #[test]
+    fn invert_flips_around_the_middle() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.set_invert(true);
+        lfo.set_gain(0.5);
+        assert_eq!(take(&mut lfo, 4), [0.5, 0.375, 0.25, 0.125]);
+        lfo.set_output_range(100.0, 200.0);
+        assert_eq!(take(&mut lfo, 2), [150.0, 137.5]);
+        lfo.set_invert(false);
+        assert_eq!(take(&mut lfo, 2), [125.0, 137.5]);
+    }
+
+    #[test]
+    fn output_range_and_curves() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        lfo.set_output_range(200.0, 8000.0);
+        assert_eq!(take(&mut lfo, 4), [200.0, 2150.0, 4100.0, 6050.0]);
+        lfo.set_range_curve(RangeCurve::Exponential);
+        let out = take(&mut lfo, 4);
+        assert!((out[0] - 200.0).abs() < 1e-9 && (out[2] - 200.0 * 40f64.sqrt()).abs() < 1e-9);
+        lfo.set_range_curve(RangeCurve::Logarithmic);
+        let out = take(&mut lfo, 4);
+        assert!((out[0] - 200.0).abs() < 1e-9 && (out[2] - (8200.0 - 200.0 * 40f64.sqrt())).abs() < 1e-9);
+        lfo.set_output_range(-24.0, 0.0);
+        lfo.set_range_curve(RangeCurve::Decibels);
+        let out = take(&mut lfo, 4);
+        assert!((out[0] - 0.0631).abs() < 1e-4 && (out[2] - 0.2512).abs() < 1e-4, "{:?}", out);
+        lfo.set_range_curve(RangeCurve::Linear);
+
+        // bipolar depth swings around the middle of the range
+        lfo.set_output_mode(OutputMode::Bipolar);
+        lfo.set_gain(0.5);
+        lfo.set_output_range(-1.0, 1.0);
+        assert_eq!(take(&mut lfo, 4), [-0.5, -0.25, 0.0, 0.25]);
+        lfo.clear_output_range();
+        assert_eq!(take(&mut lfo, 1), [-0.5]);
+    }
+
+    #[test]
+    fn tempo_changes_glide() {
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 1000.0);
+        lfo.set_tempo_follow(0.1);
+        lfo.set_tempo(120.0);
+        assert_eq!(lfo.freq, 2.0);
+        lfo.set_tempo(60.0);
+        assert_eq!(lfo.freq, 2.0);
+        take(&mut lfo, 100);
+        assert!((lfo.freq - (1.0 + (-1.0f64).exp())).abs() < 0.01, "{}", lfo.freq);
+        take(&mut lfo, 2000);
+        assert_eq!(lfo.freq, 1.0);
+        lfo.set_tempo(120.0);
+        lfo.set_rate_division(NoteValue::Half);
+        assert_eq!(lfo.freq, 1.0);
+        lfo.set_tempo_follow(0.0);
+        lfo.set_tempo(60.0);
+        assert_eq!(lfo.freq, 0.5);
+    }
+
+    #[test]
+    fn rate_by_period() {
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 1000.0);
+        lfo.set_period_secs(8.0);
+        assert_eq!(lfo.freq, 0.125);
+        assert_eq!(lfo.period_ms(), 8000.0);
+        lfo.set_tempo(120.0);
+        lfo.set_period_ms(250.0);
+        assert_eq!(lfo.freq, 4.0);
+        assert_eq!(lfo.period_secs(), 0.25);
+        lfo.set_tempo(60.0);
+        assert_eq!(lfo.period_secs(), 1.0);
+        for period in [0.0, -1.0, f64::INFINITY, f64::NAN] {
+            lfo.set_period_secs(period);
+        }
+        assert_eq!(lfo.freq, 1.0);
+        lfo.set_freq(-4.0);
+        assert_eq!(lfo.period_ms(), 250.0);
+        lfo.set_freq(0.0);
+        assert_eq!(lfo.period_secs(), f64::INFINITY);
+    }
+
+    #[test]
+    fn group_members_stay_locked_to_the_master() {
+        let sample_rate = 48000.0;
+        let mut group = LfoGroup::new(0.37, sample_rate);
+        group.add(LFO::new(Waveform::SawUp, 1.0, sample_rate), 3, 2);
+        group.add(LFO::new(Waveform::SawUp, 1.0, sample_rate), 5, 4);
+        assert_eq!(group.len(), 2);
+        let mut out = [0.0; 2];
+        for _ in 0..2_000_000 {
+            group.next_frame(&mut out);
+        }
+        let master = group.cycles as f64 + group.phase();
+        for (i, ratio) in [1.5, 1.25].into_iter().enumerate() {
+            let expected = (master * ratio).fract();
+            let phase = group.lfo_mut(i).phase();
+            assert!((phase - expected).abs() < 1e-6, "{} {}", phase, expected);
+        }
+        // each member's cycles are counted in step with the master
+        assert_eq!(group.lfo_mut(1).cycles(), (group.cycles as f64 * 1.25 + 1.25 * group.phase()) as u64);
+
+        group.set_freq(2.0);
+        assert_eq!(group.lfo_mut(0).freq, 3.0);
+        group.reset();
+        group.next_frame(&mut out);
+        assert_eq!(out, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn transport_position_sets_the_phase() {
+        let mut lfo = LFO::new(Waveform::SawUp, 3.0, 1000.0);
+        lfo.set_rate_division(NoteValue::Half);
+        // a quarter note at 120bpm is 500 samples; 1.5 half notes in
+        lfo.set_transport(1500.0, 120.0, true);
+        assert_eq!(lfo.freq, 1.0);
+        assert!((lfo.phase() - 0.5).abs() < 1e-9);
+        take(&mut lfo, 250);
+        // looping back gives the same phase as the first pass
+        lfo.set_transport(1500.0, 120.0, true);
+        assert!((lfo.phase() - 0.5).abs() < 1e-9);
+        assert_eq!(lfo.cycles, 0);
+        let first = lfo.next();
+        lfo.set_transport(1750.0, 120.0, false);
+        assert!(!lfo.is_frozen());
+        assert_eq!(lfo.next(), first);
+        // a freeze of its own outlasts the transport starting again
+        lfo.freeze();
+        lfo.set_transport(1750.0, 120.0, true);
+        assert!(lfo.is_frozen());
+        assert_eq!(lfo.next(), first);
+        lfo.unfreeze();
+        assert_ne!(lfo.next(), first);
+    }
+
+    #[test]
+    fn sync_restarts_the_cycle() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 4.0);
+        take(&mut lfo, 3);
+        lfo.sync();
+        assert_eq!(take(&mut lfo, 2), [0.0, 0.25]);
+        assert_eq!(lfo.cycles, 0);
+    }
+
+    #[test]
+    fn sample_hold_holds_within_step() {
+        let mut lfo = LFO::new(Waveform::SampleHold(1), 10.0, 1000.0);
+        let first: Vec<f64> = (0..100).map(|_| lfo.next()).collect();
+        assert!(first.iter().all(|v| *v == first[0]));
+        assert_ne!(lfo.next(), first[0]);
     }
 }
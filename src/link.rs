@@ -0,0 +1,109 @@
+// Following an Ableton Link session: the session tempo drives the LFO, and its cycle is lined up
+// with the session's beat time so every peer's LFO reaches the top of the bar together.
+use rusty_link::{AblLink, SessionState};
+
+use crate::LFO;
+
+pub struct LinkSync {
+    link: AblLink,
+    state: SessionState,
+    quantum: f64, // beats per bar
+}
+
+impl LinkSync {
+    // joins (or starts) a session, proposing `bpm` if there are no peers yet
+    pub fn new(bpm: f64, quantum: f64) -> Self {
+        let link = AblLink::new(bpm);
+        link.enable(true);
+        LinkSync { link, state: SessionState::new(), quantum }
+    }
+
+    pub fn link(&self) -> &AblLink {
+        &self.link
+    }
+
+    pub fn set_quantum(&mut self, quantum: f64) {
+        self.quantum = quantum;
+    }
+
+    // with start/stop sync on, the LFO holds while the session is stopped and picks up on the
+    // session timeline when it plays again
+    pub fn set_start_stop_sync(&mut self, enabled: bool) {
+        self.link.enable_start_stop_sync(enabled);
+    }
+
+    pub fn tempo(&self) -> f64 {
+        self.state.tempo()
+    }
+
+    // beats into the current bar, 0.0..quantum
+    pub fn bar_phase(&self) -> f64 {
+        self.state.phase_at_time(self.link.clock_micros(), self.quantum)
+    }
+
+    // call from the audio thread once per block, before rendering it; the phase is snapped onto
+    // the session timeline (Link already smooths its clock, so there's nothing to filter here)
+    pub fn sync(&mut self, lfo: &mut LFO) {
+        self.link.capture_audio_session_state(&mut self.state);
+        let time = self.link.clock_micros();
+        let playing = !self.link.is_start_stop_sync_enabled() || self.state.is_playing();
+        let beat = self.state.beat_at_time(time, self.quantum);
+        follow(lfo, self.state.tempo(), beat, playing);
+    }
+}
+
+// the session's tempo and beat time, applied to the LFO
+fn follow(lfo: &mut LFO, tempo: f64, beat: f64, playing: bool) {
+    lfo.set_transport_stopped(!playing);
+    if !playing {
+        return;
+    }
+    lfo.set_tempo(tempo);
+    let error = lfo.beat_error(beat);
+    lfo.nudge_phase(error);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Waveform;
+    use dasp_signal::Signal;
+
+    #[test]
+    fn follows_session_tempo_and_beat() {
+        let mut lfo = LFO::new(Waveform::SawUp, 0.3, 1000.0);
+        follow(&mut lfo, 120.0, 1.25, true);
+        assert_eq!(lfo.freq, 2.0);
+        assert!((lfo.phase() - 0.25).abs() < 1e-9);
+
+        // later beats only nudge the phase, never counting a cycle
+        for _ in 0..100 {
+            lfo.next();
+        }
+        follow(&mut lfo, 90.0, 7.5, true);
+        assert_eq!(lfo.freq, 1.5);
+        assert!((lfo.phase() - 0.5).abs() < 1e-9);
+        assert_eq!(lfo.cycles, 0);
+    }
+
+    #[test]
+    fn a_stopped_session_holds_the_output() {
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 1000.0);
+        follow(&mut lfo, 60.0, 0.25, true);
+        let held = lfo.next();
+        follow(&mut lfo, 60.0, 0.5, false);
+        assert_eq!(lfo.next(), held);
+        follow(&mut lfo, 60.0, 0.75, false);
+        assert_eq!(lfo.next(), held);
+        follow(&mut lfo, 60.0, 0.5, true);
+        assert_eq!(lfo.next(), 0.5);
+
+        // a freeze of the user's own outlasts the session starting again
+        lfo.freeze();
+        let frozen = lfo.next();
+        follow(&mut lfo, 60.0, 0.5, false);
+        follow(&mut lfo, 60.0, 0.75, true);
+        assert!(lfo.is_frozen());
+        assert_eq!(lfo.next(), frozen);
+    }
+}
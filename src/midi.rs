@@ -0,0 +1,384 @@
+// Following an external MIDI clock: 24 ticks per quarter note plus start, stop and continue.
+// Driving hardware the other way, with the LFO output sent as controller values.
+use crate::LFO;
+
+pub const PPQN: f64 = 24.0;
+
+const CLOCK: u8 = 0xF8;
+const START: u8 = 0xFA;
+const CONTINUE: u8 = 0xFB;
+const STOP: u8 = 0xFC;
+const CONTROL_CHANGE: u8 = 0xB0;
+const PITCH_BEND: u8 = 0xE0;
+// the controller carrying the low seven bits of a 14-bit pair is this far above the high one
+const LSB_CONTROLLER: u8 = 32;
+const HIGH_RES_MAX: u16 = 0x3FFF;
+
+// how much of the tick spacing estimate each new tick replaces, smoothing out jitter
+const TEMPO_FOLLOW: f64 = 0.1;
+// this many hiccups in a row are a real tempo change after all
+const HICCUP_LIMIT: u32 = 3;
+// fraction of the phase error corrected per sample
+const PHASE_FOLLOW: f64 = 0.002;
+
+// feed it the realtime bytes as they arrive and call sync() once per sample before reading the
+// LFO; the LFO follows the clock's tempo at its own rate division, its phase pulled onto the
+// song position so it doesn't wander from the sequencer over time
+pub struct MidiClock {
+    sample_rate: f64,
+    running: bool,
+    ticks: u64, // since the last start
+    since_tick: f64, // samples since the last tick
+    tick_samples: Option<f64>, // smoothed spacing between ticks
+    hiccups: u32, // ticks in a row too far from the estimate to be trusted
+    restart: Option<bool>, // pending transport change for the LFO: true to start over, false to continue
+    stopped: bool, // pending stop
+}
+
+impl MidiClock {
+    pub fn new(sample_rate: f64) -> Self {
+        MidiClock {
+            sample_rate,
+            running: false,
+            ticks: 0,
+            since_tick: 0.0,
+            tick_samples: None,
+            hiccups: 0,
+            restart: None,
+            stopped: false,
+        }
+    }
+
+    // system realtime status bytes; anything else is ignored
+    pub fn message(&mut self, status: u8) {
+        match status {
+            CLOCK => self.tick(),
+            START => {
+                self.running = true;
+                self.ticks = 0;
+                self.since_tick = 0.0;
+                self.hiccups = 0;
+                self.restart = Some(true);
+            },
+            CONTINUE => {
+                self.running = true;
+                self.restart = Some(false);
+            },
+            STOP => {
+                self.running = false;
+                self.stopped = true;
+            },
+            _ => {},
+        }
+    }
+
+    // clocks sent while stopped are ignored, so a continue picks up where the stop left off
+    fn tick(&mut self) {
+        if !self.running {
+            return;
+        }
+        // the first tick after a start only marks time zero
+        if self.ticks > 0 {
+            let spacing = self.since_tick;
+            let hiccup = self.tick_samples.is_some_and(|estimate| (spacing - estimate).abs() > estimate);
+            self.hiccups = if hiccup { self.hiccups + 1 } else { 0 };
+            self.tick_samples = Some(match self.tick_samples {
+                // a tick more than twice as far off as expected is a hiccup, not a tempo change,
+                // unless it keeps happening
+                Some(estimate) if hiccup && self.hiccups < HICCUP_LIMIT => estimate,
+                Some(estimate) if !hiccup => estimate + (spacing - estimate) * TEMPO_FOLLOW,
+                _ => {
+                    self.hiccups = 0;
+                    spacing
+                },
+            });
+        }
+        self.ticks += 1;
+        self.since_tick = 0.0;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn bpm(&self) -> Option<f64> {
+        self.tick_samples.map(|samples| 60.0 * self.sample_rate / (samples * PPQN))
+    }
+
+    // quarter notes since the last start, interpolated between ticks
+    pub fn position(&self) -> f64 {
+        let between = match self.tick_samples {
+            Some(samples) => (self.since_tick / samples).min(1.0),
+            None => 0.0,
+        };
+        (self.ticks.saturating_sub(1) as f64 + between) / PPQN
+    }
+
+    pub fn sync(&mut self, lfo: &mut LFO) {
+        if self.stopped {
+            self.stopped = false;
+            lfo.set_transport_stopped(true);
+        }
+        match self.restart.take() {
+            Some(true) => {
+                lfo.reset();
+                lfo.set_transport_stopped(false);
+            },
+            Some(false) => lfo.set_transport_stopped(false),
+            None => {},
+        }
+        if !self.running {
+            return;
+        }
+        if let Some(bpm) = self.bpm() {
+            lfo.set_tempo(bpm);
+            if self.ticks > 0 {
+                let error = lfo.beat_error(self.position());
+                lfo.nudge_phase(error * PHASE_FOLLOW);
+            }
+        }
+        self.since_tick += 1.0;
+    }
+}
+
+// a control change message for `value` on `channel` 0..16
+pub fn control_change(channel: u8, controller: u8, value: u8) -> [u8; 3] {
+    [CONTROL_CHANGE | (channel & 0x0F), controller & 0x7F, value & 0x7F]
+}
+
+// a 14-bit controller pair, the high seven bits on `controller` 0..32 and the low on the one 32 above
+pub fn control_change_14(channel: u8, controller: u8, value: u16) -> [[u8; 3]; 2] {
+    let (msb, lsb) = split(value);
+    let controller = controller & 0x1F;
+    [control_change(channel, controller, msb), control_change(channel, controller + LSB_CONTROLLER, lsb)]
+}
+
+pub fn pitch_bend(channel: u8, value: u16) -> [u8; 3] {
+    let (msb, lsb) = split(value);
+    [PITCH_BEND | (channel & 0x0F), lsb, msb]
+}
+
+// the high and low seven bits of a 14-bit value
+pub fn split(value: u16) -> (u8, u8) {
+    let value = value.min(HIGH_RES_MAX);
+    ((value >> 7) as u8, (value & 0x7F) as u8)
+}
+
+// passes on changed values, no more often than once every `interval` samples
+struct Throttle<T> {
+    interval: f64,
+    since: f64, // samples since the last value went out
+    last: Option<T>,
+}
+
+impl<T: Copy + PartialEq> Throttle<T> {
+    fn new() -> Self {
+        Throttle { interval: 0.0, since: 0.0, last: None }
+    }
+
+    fn set_max_rate(&mut self, sample_rate: f64, per_second: f64) {
+        self.interval = if per_second > 0.0 { sample_rate / per_second } else { 0.0 };
+    }
+
+    // a change held back by the limit goes out as soon as it can
+    fn update(&mut self, value: T) -> Option<T> {
+        self.since += 1.0;
+        if self.last == Some(value) || (self.last.is_some() && self.since < self.interval) {
+            return None;
+        }
+        self.last = Some(value);
+        self.since = 0.0;
+        Some(value)
+    }
+}
+
+// turns a 0.0..1.0 output into 7-bit controller values, call update() once per sample; only
+// changes are reported, and no more often than the rate limit allows, so a slow LFO doesn't
+// flood the port with repeats
+pub struct CcOutput {
+    sample_rate: f64,
+    levels: u8, // distinct values used, spread over 0..=127
+    changes: Throttle<u8>,
+}
+
+impl CcOutput {
+    pub fn new(sample_rate: f64) -> Self {
+        CcOutput { sample_rate, levels: 128, changes: Throttle::new() }
+    }
+
+    // fewer levels for coarse parameters, e.g. 8 for a switch with eight positions
+    pub fn set_resolution(&mut self, levels: u8) {
+        self.levels = levels.clamp(2, 128);
+    }
+
+    // messages per second at most; 0.0 lifts the limit
+    pub fn set_max_rate(&mut self, per_second: f64) {
+        self.changes.set_max_rate(self.sample_rate, per_second);
+    }
+
+    // the value last reported
+    pub fn value(&self) -> Option<u8> {
+        self.changes.last
+    }
+
+    pub fn quantize(&self, value: f64) -> u8 {
+        let top = self.levels as f64 - 1.0;
+        let level = (value.clamp(0.0, 1.0) * top).round();
+        (level * 127.0 / top).round() as u8
+    }
+
+    // a value to send, if it has changed and the rate limit allows
+    pub fn update(&mut self, value: f64) -> Option<u8> {
+        let value = self.quantize(value);
+        self.changes.update(value)
+    }
+}
+
+// the same for 14-bit values, 0..=16383 with 8192 in the middle, for pitch bend and controller
+// pairs where 7-bit steps would be audible
+pub struct HighResOutput {
+    sample_rate: f64,
+    changes: Throttle<u16>,
+}
+
+impl HighResOutput {
+    pub fn new(sample_rate: f64) -> Self {
+        HighResOutput { sample_rate, changes: Throttle::new() }
+    }
+
+    pub fn set_max_rate(&mut self, per_second: f64) {
+        self.changes.set_max_rate(self.sample_rate, per_second);
+    }
+
+    pub fn value(&self) -> Option<u16> {
+        self.changes.last
+    }
+
+    pub fn quantize(&self, value: f64) -> u16 {
+        (value.clamp(0.0, 1.0) * HIGH_RES_MAX as f64).round() as u16
+    }
+
+    pub fn update(&mut self, value: f64) -> Option<u16> {
+        let value = self.quantize(value);
+        self.changes.update(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Waveform;
+    use crate::tempo::NoteValue;
+    use dasp_signal::Signal;
+
+    // 120bpm at 48kHz is one tick every 1000 samples
+    fn run(clock: &mut MidiClock, lfo: &mut LFO, ticks: usize, spacing: usize) {
+        for _ in 0..ticks {
+            clock.message(CLOCK);
+            for _ in 0..spacing {
+                clock.sync(lfo);
+                lfo.next();
+            }
+        }
+    }
+
+    #[test]
+    fn follows_tempo_and_locks_phase() {
+        let mut clock = MidiClock::new(48000.0);
+        let mut lfo = LFO::new(Waveform::Sine, 0.3, 48000.0);
+        lfo.set_rate_division(NoteValue::Half);
+        clock.message(START);
+        run(&mut clock, &mut lfo, 24 * 40, 1000);
+        assert!((clock.bpm().unwrap() - 120.0).abs() < 1e-9);
+        assert!((lfo.freq - 1.0).abs() < 1e-9);
+        let error = |lfo: &LFO, clock: &MidiClock| {
+            let target = (clock.position() / 2.0).fract();
+            ((lfo.phase() - target + 0.5).rem_euclid(1.0) - 0.5).abs()
+        };
+        assert!(error(&lfo, &clock) < 1e-3);
+
+        // the sequencer drifts a little faster; the LFO comes along
+        run(&mut clock, &mut lfo, 24 * 40, 990);
+        assert!((clock.bpm().unwrap() - 120.0 * 1000.0 / 990.0).abs() < 0.01);
+        assert!(error(&lfo, &clock) < 2e-3);
+    }
+
+    #[test]
+    fn hiccups_are_ignored_but_a_tempo_drop_is_not() {
+        let mut clock = MidiClock::new(48000.0);
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 48000.0);
+        clock.message(START);
+        run(&mut clock, &mut lfo, 24, 1000);
+        run(&mut clock, &mut lfo, 1, 2500);
+        run(&mut clock, &mut lfo, 1, 1000);
+        assert!((clock.bpm().unwrap() - 120.0).abs() < 1e-9);
+
+        // 120 down to 48bpm
+        run(&mut clock, &mut lfo, 24 * 4, 2500);
+        assert!((clock.bpm().unwrap() - 48.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cc_reports_changes_within_the_rate_limit() {
+        let mut cc = CcOutput::new(1000.0);
+        assert_eq!(cc.update(0.5), Some(64));
+        assert_eq!(cc.update(0.501), None);
+        cc.set_max_rate(100.0);
+        let sent: Vec<Option<u8>> = (1..=20).map(|i| cc.update(0.5 + i as f64 * 0.01)).collect();
+        assert_eq!(sent.iter().flatten().count(), 2);
+        assert_eq!(sent[8], Some(75));
+        assert_eq!(cc.value(), Some(88));
+
+        cc.set_resolution(3);
+        assert_eq!([cc.quantize(0.2), cc.quantize(0.3), cc.quantize(1.5)], [0, 64, 127]);
+        assert_eq!(control_change(17, 74, 200), [0xB1, 74, 72]);
+    }
+
+    #[test]
+    fn high_res_splits_into_seven_bit_halves() {
+        let mut bend = HighResOutput::new(1000.0);
+        assert_eq!(bend.update(0.5), Some(8192));
+        assert_eq!(bend.update(0.50001), None);
+        assert_eq!(bend.update(0.5001), Some(8193));
+        bend.set_max_rate(10.0);
+        assert_eq!(bend.update(1.0), None);
+        assert_eq!(bend.quantize(-1.0), 0);
+        assert_eq!(split(8193), (64, 1));
+        assert_eq!(split(u16::MAX), (127, 127));
+        assert_eq!(pitch_bend(0, 8192), [0xE0, 0, 64]);
+        assert_eq!(control_change_14(2, 1, 0x3FFF), [[0xB2, 1, 127], [0xB2, 33, 127]]);
+    }
+
+    #[test]
+    fn stop_continue_and_start() {
+        let mut clock = MidiClock::new(48000.0);
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 48000.0);
+        clock.message(START);
+        run(&mut clock, &mut lfo, 30, 1000);
+        clock.message(STOP);
+        clock.sync(&mut lfo);
+        let held = lfo.next();
+        assert!(!clock.is_running());
+        run(&mut clock, &mut lfo, 2, 1000);
+        assert_eq!(lfo.next(), held);
+        clock.message(CONTINUE);
+        clock.sync(&mut lfo);
+        assert!(lfo.next() > held);
+
+        // a freeze of the user's own outlasts a stop and continue
+        lfo.freeze();
+        let frozen = lfo.next();
+        clock.message(STOP);
+        clock.sync(&mut lfo);
+        clock.message(CONTINUE);
+        run(&mut clock, &mut lfo, 2, 1000);
+        assert!(lfo.is_frozen());
+        assert_eq!(lfo.next(), frozen);
+        lfo.unfreeze();
+        clock.message(START);
+        clock.sync(&mut lfo);
+        assert_eq!(lfo.next(), 0.0);
+        clock.message(0x90);
+        assert!(clock.is_running());
+    }
+}
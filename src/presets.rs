@@ -0,0 +1,98 @@
+// Ready-made creative shapes, built from breakpoints and wavetables.
+use crate::{Breakpoint, Interpolation, Waveform};
+
+pub const NAMES: &[&str] = &[
+    "heartbeat",
+    "ramp-hold",
+    "double-dip",
+    "sidechain-duck",
+    "stutter",
+];
+
+pub fn preset(name: &str) -> Option<Waveform> {
+    let waveform = match name {
+        "heartbeat" => heartbeat(),
+        "ramp-hold" => ramp_hold(),
+        "double-dip" => double_dip(),
+        "sidechain-duck" => sidechain_duck(),
+        "stutter" => stutter(),
+        _ => return None,
+    };
+    Some(waveform)
+}
+
+// two quick beats, lub-dub, then rest
+pub fn heartbeat() -> Waveform {
+    Waveform::Segments(vec![
+        Breakpoint::new(0.0, -1.0, 0.0),
+        Breakpoint::new(0.05, 1.0, 0.5),
+        Breakpoint::new(0.15, -0.6, 0.0),
+        Breakpoint::new(0.22, 0.5, 0.5),
+        Breakpoint::new(0.35, -1.0, 0.0),
+    ])
+}
+
+// rise over half the cycle, hold at the top, then drop
+pub fn ramp_hold() -> Waveform {
+    Waveform::Segments(vec![
+        Breakpoint::new(0.0, -1.0, 0.0),
+        Breakpoint::new(0.5, 1.0, 0.0),
+        Breakpoint::new(0.98, 1.0, 0.0),
+    ])
+}
+
+// two dips towards the bottom per cycle, the second shallower
+pub fn double_dip() -> Waveform {
+    let table = (0..256).map(|i| {
+        let phase = i as f64 / 256.0;
+        let dip = |centre: f64, depth: f64| depth * (-((phase - centre) / 0.06).powi(2)).exp();
+        1.0 - 2.0 * dip(0.25, 1.0) - 2.0 * dip(0.7, 0.6)
+    }).collect();
+    Waveform::Wavetable { table, interpolation: Interpolation::Linear }
+}
+
+// instant duck to the bottom then an exponential recovery, like a compressor keyed by a kick
+pub fn sidechain_duck() -> Waveform {
+    Waveform::Segments(vec![
+        Breakpoint::new(0.0, -1.0, -0.6),
+        Breakpoint::new(0.6, 1.0, 0.0),
+        Breakpoint::new(0.99, 1.0, 0.0),
+    ])
+}
+
+// four short gates with soft edges in the first half, silence in the second
+pub fn stutter() -> Waveform {
+    let mut points = Vec::new();
+    for i in 0..4 {
+        let start = i as f64 * 0.125;
+        points.push(Breakpoint::new(start, -1.0, 0.0));
+        points.push(Breakpoint::new(start + 0.01, 1.0, 0.0));
+        points.push(Breakpoint::new(start + 0.06, 1.0, 0.0));
+        points.push(Breakpoint::new(start + 0.07, -1.0, 0.0));
+    }
+    Waveform::Segments(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LFO;
+    use dasp_signal::Signal;
+
+    #[test]
+    fn every_name_resolves_and_stays_in_range() {
+        for name in NAMES {
+            let waveform = preset(name).unwrap_or_else(|| panic!("missing preset {}", name));
+            let mut lfo = LFO::new(waveform, 1.0, 1000.0);
+            assert!((0..1000).map(|_| lfo.next()).all(|v| (-1e-9..=1.0 + 1e-9).contains(&v)), "{}", name);
+        }
+        assert!(preset("no-such-shape").is_none());
+    }
+
+    #[test]
+    fn sidechain_duck_starts_at_the_bottom() {
+        let mut lfo = LFO::new(sidechain_duck(), 1.0, 1000.0);
+        assert_eq!(lfo.next(), 0.0);
+        assert!((0..700).map(|_| lfo.next()).last().unwrap() > 0.99);
+    }
+}
@@ -0,0 +1,116 @@
+// Scales for snapping the output to pitches, in semitones from the key's C.
+pub const NAMES: &[&str] = &[
+    "chromatic",
+    "major",
+    "minor",
+    "harmonic-minor",
+    "major-pentatonic",
+    "minor-pentatonic",
+];
+
+// bit i of `mask` allows the note i semitones above `root`, a pitch class 0..12 with 0 as C
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scale {
+    mask: u16,
+    root: u8,
+}
+
+impl Scale {
+    pub fn new(degrees: &[u8], root: u8) -> Self {
+        let mask = degrees.iter().fold(0, |mask, degree| mask | 1 << (degree % 12));
+        Self::from_mask(mask, root)
+    }
+
+    pub fn from_mask(mask: u16, root: u8) -> Self {
+        Scale { mask: mask & 0xFFF, root: root % 12 }
+    }
+
+    pub fn chromatic() -> Self {
+        Self::from_mask(0xFFF, 0)
+    }
+
+    pub fn major(root: u8) -> Self {
+        Self::new(&[0, 2, 4, 5, 7, 9, 11], root)
+    }
+
+    pub fn minor(root: u8) -> Self {
+        Self::new(&[0, 2, 3, 5, 7, 8, 10], root)
+    }
+
+    pub fn harmonic_minor(root: u8) -> Self {
+        Self::new(&[0, 2, 3, 5, 7, 8, 11], root)
+    }
+
+    pub fn major_pentatonic(root: u8) -> Self {
+        Self::new(&[0, 2, 4, 7, 9], root)
+    }
+
+    pub fn minor_pentatonic(root: u8) -> Self {
+        Self::new(&[0, 3, 5, 7, 10], root)
+    }
+
+    pub fn contains(self, semitone: i64) -> bool {
+        self.mask & 1 << semitone.wrapping_sub(self.root as i64).rem_euclid(12) != 0
+    }
+
+    // the nearest note in the scale, the lower one on a tie; an empty scale lets everything
+    // through, as do infinities and NaN
+    pub fn quantize(self, semitones: f64) -> f64 {
+        if self.mask == 0 || !semitones.is_finite() {
+            return semitones;
+        }
+        let centre = semitones.round() as i64;
+        let mut best = None;
+        for note in (centre.saturating_sub(6)..=centre.saturating_add(6)).filter(|note| self.contains(*note)) {
+            let distance = (note as f64 - semitones).abs();
+            if best.is_none_or(|(_, d)| distance < d) {
+                best = Some((note, distance));
+            }
+        }
+        best.map_or(semitones, |(note, _)| note as f64)
+    }
+}
+
+pub fn scale(name: &str, root: u8) -> Option<Scale> {
+    let scale = match name {
+        "chromatic" => Scale::chromatic(),
+        "major" => Scale::major(root),
+        "minor" => Scale::minor(root),
+        "harmonic-minor" => Scale::harmonic_minor(root),
+        "major-pentatonic" => Scale::major_pentatonic(root),
+        "minor-pentatonic" => Scale::minor_pentatonic(root),
+        _ => return None,
+    };
+    Some(scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snaps_to_the_nearest_note_in_key() {
+        let c_major = Scale::major(0);
+        assert_eq!(c_major.quantize(1.4), 2.0);
+        assert_eq!(c_major.quantize(6.0), 5.0);
+        assert_eq!(c_major.quantize(-1.2), -1.0);
+        assert_eq!(c_major.quantize(13.0), 12.0);
+        let d_minor = Scale::minor(2);
+        assert!(d_minor.contains(10) && !d_minor.contains(11));
+        assert_eq!(d_minor.quantize(10.4), 10.0);
+        assert_eq!(d_minor.quantize(11.4), 12.0);
+        assert_eq!(Scale::from_mask(0, 0).quantize(0.3), 0.3);
+        assert_eq!(c_major.quantize(f64::INFINITY), f64::INFINITY);
+        assert!(c_major.quantize(f64::NAN).is_nan());
+        assert!(c_major.quantize(1e300).is_finite() && d_minor.quantize(-1e300).is_finite());
+    }
+
+    #[test]
+    fn every_name_resolves() {
+        for name in NAMES {
+            assert!(scale(name, 0).is_some(), "{}", name);
+        }
+        assert_eq!(scale("minor", 14), Some(Scale::minor(2)));
+        assert!(scale("lydian-augmented", 0).is_none());
+    }
+}
@@ -0,0 +1,371 @@
+// Deriving the LFO rate from a musical tempo, tapped, clocked or given.
+use std::time::Instant;
+
+use crate::LFO;
+
+// length of one LFO cycle as a note value, in 4/4
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoteValue {
+    Bars(u32),
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+}
+
+impl NoteValue {
+    // length in quarter-note beats
+    pub fn beats(self) -> f64 {
+        match self {
+            NoteValue::Bars(bars) => 4.0 * bars.max(1) as f64,
+            NoteValue::Whole => 4.0,
+            NoteValue::Half => 2.0,
+            NoteValue::Quarter => 1.0,
+            NoteValue::Eighth => 0.5,
+            NoteValue::Sixteenth => 0.25,
+            NoteValue::ThirtySecond => 0.125,
+        }
+    }
+
+    // cycles per second at `bpm` quarter notes per minute
+    pub fn freq(self, bpm: f64) -> f64 {
+        bpm / 60.0 / self.beats()
+    }
+
+    pub fn dotted(self) -> Division {
+        Division { note: self, modifier: Modifier::Dotted }
+    }
+
+    pub fn triplet(self) -> Division {
+        Division { note: self, modifier: Modifier::Triplet }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Modifier {
+    Straight,
+    Dotted, // half as long again
+    Triplet, // three in the time of two
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Division {
+    pub note: NoteValue,
+    pub modifier: Modifier,
+}
+
+impl Division {
+    // "1/8", "1/8D" and "1/8T" style names, with "2/1" and so on for whole bars
+    pub fn parse(name: &str) -> Option<Division> {
+        let name = name.trim();
+        let (name, modifier) = match name.as_bytes().last()? {
+            b'D' | b'd' => (&name[..name.len() - 1], Modifier::Dotted),
+            b'T' | b't' => (&name[..name.len() - 1], Modifier::Triplet),
+            _ => (name, Modifier::Straight),
+        };
+        let (num, den) = name.split_once('/')?;
+        let note = match (num.trim().parse::<u32>().ok()?, den.trim().parse::<u32>().ok()?) {
+            (bars, 1) if bars > 1 => NoteValue::Bars(bars),
+            (1, 1) => NoteValue::Whole,
+            (1, 2) => NoteValue::Half,
+            (1, 4) => NoteValue::Quarter,
+            (1, 8) => NoteValue::Eighth,
+            (1, 16) => NoteValue::Sixteenth,
+            (1, 32) => NoteValue::ThirtySecond,
+            _ => return None,
+        };
+        Some(Division { note, modifier })
+    }
+
+    pub fn beats(self) -> f64 {
+        let scale = match self.modifier {
+            Modifier::Straight => 1.0,
+            Modifier::Dotted => 1.5,
+            Modifier::Triplet => 2.0 / 3.0,
+        };
+        self.note.beats() * scale
+    }
+
+    pub fn freq(self, bpm: f64) -> f64 {
+        bpm / 60.0 / self.beats()
+    }
+}
+
+impl From<NoteValue> for Division {
+    fn from(note: NoteValue) -> Self {
+        Division { note, modifier: Modifier::Straight }
+    }
+}
+
+// intervals further than this fraction from the median are treated as mistimed taps
+const OUTLIER: f64 = 0.3;
+const MAX_INTERVALS: usize = 8;
+
+// averages the intervals between taps; a pause longer than `timeout` seconds starts a new count
+pub struct TapTempo {
+    intervals: Vec<f64>,
+    last: Option<f64>,
+    timeout: f64,
+    origin: Instant, // zero for tap_now() timestamps
+}
+
+impl TapTempo {
+    pub fn new() -> Self {
+        TapTempo { intervals: Vec::new(), last: None, timeout: 2.0, origin: Instant::now() }
+    }
+
+    pub fn set_timeout(&mut self, secs: f64) {
+        self.timeout = secs;
+    }
+
+    // `time` in seconds from any fixed point; returns the tapped rate in Hz once there are two taps
+    pub fn tap(&mut self, time: f64) -> Option<f64> {
+        match self.last {
+            Some(last) if time > last && time - last <= self.timeout => {
+                if self.intervals.len() == MAX_INTERVALS {
+                    self.intervals.remove(0);
+                }
+                self.intervals.push(time - last);
+            },
+            _ => self.intervals.clear(),
+        }
+        self.last = Some(time);
+        self.freq()
+    }
+
+    pub fn tap_now(&mut self) -> Option<f64> {
+        let time = self.origin.elapsed().as_secs_f64();
+        self.tap(time)
+    }
+
+    // mean of the intervals close to the median, in seconds
+    pub fn interval(&self) -> Option<f64> {
+        let mut sorted = self.intervals.clone();
+        sorted.sort_by(f64::total_cmp);
+        let median = *sorted.get(sorted.len() / 2)?;
+        let kept: Vec<f64> = sorted.into_iter().filter(|i| (i - median).abs() <= OUTLIER * median).collect();
+        Some(kept.iter().sum::<f64>() / kept.len() as f64)
+    }
+
+    pub fn freq(&self) -> Option<f64> {
+        self.interval().map(|i| 1.0 / i)
+    }
+
+    pub fn bpm(&self) -> Option<f64> {
+        self.freq().map(|f| 60.0 * f)
+    }
+
+    // sets the LFO to one cycle per tap, leaving it alone until there is a rate
+    pub fn apply(&self, lfo: &mut LFO) {
+        if let Some(freq) = self.freq() {
+            lfo.set_freq(freq);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.intervals.clear();
+        self.last = None;
+    }
+}
+
+impl Default for TapTempo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// how many pulses one LFO cycle spans, or how many cycles fit between two pulses
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockRatio {
+    Divide(u32),
+    Multiply(u32),
+}
+
+impl ClockRatio {
+    fn cycle_pulses(self) -> f64 {
+        match self {
+            ClockRatio::Divide(n) => n.max(1) as f64,
+            ClockRatio::Multiply(n) => 1.0 / n.max(1) as f64,
+        }
+    }
+}
+
+// a later pulse than this, in pulse spacings, means the clock stopped rather than dropped pulses
+const MAX_MISSED: f64 = 4.5;
+
+// follows a pulse stream such as a clock input or a sequencer gate; call sync() once per sample
+// before reading the LFO. The rate comes from the spacing of the last two pulses and each pulse
+// puts the phase where that pulse falls in the cycle. A few dropped pulses are counted through,
+// a longer gap loses the lock and the clock re-locks from the next pulses
+pub struct ClockInput {
+    ratio: ClockRatio,
+    since_pulse: Option<f64>, // samples since the last pulse
+    spacing: Option<f64>, // samples between pulses while locked
+    count: u64, // pulses since locking
+}
+
+impl ClockInput {
+    pub fn new(ratio: ClockRatio) -> Self {
+        ClockInput { ratio, since_pulse: None, spacing: None, count: 0 }
+    }
+
+    pub fn set_ratio(&mut self, ratio: ClockRatio) {
+        self.ratio = ratio;
+    }
+
+    pub fn is_locked(&self) -> bool {
+        match (self.spacing, self.since_pulse) {
+            (Some(spacing), Some(since)) => since <= MAX_MISSED * spacing,
+            _ => false,
+        }
+    }
+
+    // `pulse` is true on the sample where the pulse's rising edge arrived
+    pub fn sync(&mut self, lfo: &mut LFO, pulse: bool) {
+        if pulse {
+            self.pulse(lfo);
+        }
+        if let Some(since) = &mut self.since_pulse {
+            *since += 1.0;
+        }
+    }
+
+    fn pulse(&mut self, lfo: &mut LFO) {
+        match (self.spacing, self.since_pulse) {
+            (Some(spacing), Some(since)) if since < 1.5 * spacing => {
+                self.spacing = Some(since);
+                self.count += 1;
+            },
+            (Some(spacing), Some(since)) if since <= MAX_MISSED * spacing => {
+                self.count += (since / spacing).round() as u64;
+            },
+            (None, Some(since)) => {
+                self.spacing = Some(since);
+                self.count = 1;
+            },
+            // the first pulse, or the first after losing the lock, starts the count over
+            _ => {
+                self.spacing = None;
+                self.count = 0;
+            },
+        }
+        self.since_pulse = Some(0.0);
+        let cycle_pulses = self.ratio.cycle_pulses();
+        if let Some(spacing) = self.spacing {
+            lfo.set_freq(lfo.sample_rate / (spacing * cycle_pulses));
+        }
+        let target = (self.count as f64 / cycle_pulses).fract();
+        lfo.nudge_phase((target - lfo.phase() + 0.5).rem_euclid(1.0) - 0.5);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Waveform;
+    use dasp_signal::Signal;
+
+    #[test]
+    fn averages_taps_and_rejects_outliers() {
+        let mut tap = TapTempo::new();
+        assert_eq!(tap.tap(10.0), None);
+        assert_eq!(tap.tap(10.5), Some(2.0));
+        tap.tap(11.02);
+        tap.tap(11.48);
+        // a double tap
+        tap.tap(11.6);
+        tap.tap(12.1);
+        assert!((tap.interval().unwrap() - 0.5).abs() < 0.01, "{:?}", tap.interval());
+        assert!((tap.bpm().unwrap() - 120.0).abs() < 2.0);
+
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 1000.0);
+        tap.apply(&mut lfo);
+        assert!((lfo.freq - 2.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn note_values_against_tempo() {
+        assert_eq!(NoteValue::Quarter.freq(120.0), 2.0);
+        assert_eq!(NoteValue::Sixteenth.freq(120.0), 8.0);
+        assert_eq!(NoteValue::Bars(2).freq(120.0), 0.25);
+        assert_eq!(NoteValue::Bars(0).beats(), 4.0);
+    }
+
+    #[test]
+    fn dotted_and_triplet_divisions() {
+        assert_eq!(NoteValue::Eighth.dotted().beats(), 0.75);
+        assert!((NoteValue::Quarter.triplet().freq(120.0) - 3.0).abs() < 1e-12);
+        assert_eq!(Division::parse("1/8D"), Some(NoteValue::Eighth.dotted()));
+        assert_eq!(Division::parse("1/16t"), Some(NoteValue::Sixteenth.triplet()));
+        assert_eq!(Division::parse(" 4/1 "), Some(NoteValue::Bars(4).into()));
+        assert_eq!(Division::parse("1/4"), Some(Division::from(NoteValue::Quarter)));
+        assert_eq!(Division::parse("3/8"), None);
+        assert_eq!(Division::parse(""), None);
+        assert_eq!(Division::parse("D"), None);
+    }
+
+    // pulses `spacing` samples apart; returns the saw output, which is the phase, at the last pulse
+    fn clock(input: &mut ClockInput, lfo: &mut LFO, pulses: usize, spacing: usize) -> f64 {
+        let mut at_pulse = 0.0;
+        for _ in 0..pulses {
+            input.sync(lfo, true);
+            at_pulse = lfo.next();
+            for _ in 1..spacing {
+                input.sync(lfo, false);
+                lfo.next();
+            }
+        }
+        at_pulse
+    }
+
+    #[test]
+    fn clock_divides_and_multiplies() {
+        let mut input = ClockInput::new(ClockRatio::Divide(4));
+        let mut lfo = LFO::new(Waveform::SawUp, 0.3, 1000.0);
+        let phase = clock(&mut input, &mut lfo, 7, 100);
+        assert!(input.is_locked());
+        assert_eq!(lfo.freq, 2.5);
+        assert!((phase - 0.5).abs() < 1e-9);
+
+        input.set_ratio(ClockRatio::Multiply(3));
+        let phase = clock(&mut input, &mut lfo, 3, 100);
+        assert!((lfo.freq - 30.0).abs() < 1e-9);
+        assert!(phase.min(1.0 - phase) < 1e-9);
+    }
+
+    #[test]
+    fn clock_counts_through_dropped_pulses_and_relocks() {
+        let mut input = ClockInput::new(ClockRatio::Divide(4));
+        let mut lfo = LFO::new(Waveform::SawUp, 1.0, 1000.0);
+        clock(&mut input, &mut lfo, 4, 100);
+        // the pulse after the fourth goes missing but the count carries on
+        clock(&mut input, &mut lfo, 1, 200);
+        let phase = clock(&mut input, &mut lfo, 1, 1000);
+        assert_eq!(lfo.freq, 2.5);
+        assert!((phase - 0.5).abs() < 1e-9);
+
+        // the clock stopped for long enough to lose the lock, so the next pulse starts over
+        assert!(!input.is_locked());
+        assert_eq!(clock(&mut input, &mut lfo, 1, 50), 0.0);
+        let phase = clock(&mut input, &mut lfo, 1, 50);
+        assert!(input.is_locked());
+        assert_eq!(lfo.freq, 5.0);
+        assert!((phase - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn long_pause_starts_over() {
+        let mut tap = TapTempo::new();
+        tap.tap(0.0);
+        tap.tap(1.0);
+        assert_eq!(tap.tap(5.0), None);
+        assert_eq!(tap.tap(5.25), Some(4.0));
+        tap.reset();
+        assert_eq!(tap.tap(6.0), None);
+        let mut lfo = LFO::new(Waveform::Sine, 1.0, 1000.0);
+        tap.apply(&mut lfo);
+        assert_eq!(lfo.freq, 1.0);
+    }
+}
@@ -0,0 +1,87 @@
+// Single-cycle waveforms loaded from mono WAV files.
+use std::io::Read;
+use std::path::Path;
+
+use hound::{SampleFormat, WavReader};
+
+use crate::{wavetable, Interpolation, Waveform};
+
+pub fn load_wavetable<P: AsRef<Path>>(path: P, interpolation: Interpolation) -> Result<Waveform, hound::Error> {
+    read_wavetable(WavReader::open(path)?, interpolation)
+}
+
+pub fn read_wavetable<R: Read>(reader: WavReader<R>, interpolation: Interpolation) -> Result<Waveform, hound::Error> {
+    let spec = reader.spec();
+    if spec.channels != 1 {
+        return Err(hound::Error::FormatError("expected a mono file"));
+    }
+    let samples: Vec<f64> = match spec.sample_format {
+        SampleFormat::Float => reader.into_samples::<f32>()
+            .map(|s| s.map(f64::from))
+            .collect::<Result<_, _>>()?,
+        SampleFormat::Int => {
+            let full_scale = (1u64 << (spec.bits_per_sample - 1)) as f64;
+            reader.into_samples::<i32>()
+                .map(|s| s.map(|s| s as f64 / full_scale))
+                .collect::<Result<_, _>>()?
+        },
+    };
+    if samples.is_empty() {
+        return Err(hound::Error::FormatError("no samples"));
+    }
+    Ok(Waveform::Wavetable { table: resample(&samples), interpolation })
+}
+
+// stretch the cycle onto the next power-of-two length, which keeps table lookups cheap
+fn resample(samples: &[f64]) -> Vec<f64> {
+    let len = samples.len().next_power_of_two();
+    if len == samples.len() {
+        return samples.iter().map(|s| s.clamp(-1.0, 1.0)).collect();
+    }
+    (0..len).map(|i| wavetable(i as f64 / len as f64, samples, Interpolation::Cubic)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use hound::{WavSpec, WavWriter};
+
+    fn write_wav(spec: WavSpec, samples: &[i16]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut writer = WavWriter::new(Cursor::new(&mut bytes), spec).unwrap();
+        for s in samples {
+            writer.write_sample(*s).unwrap();
+        }
+        writer.finalize().unwrap();
+        bytes
+    }
+
+    fn spec(channels: u16) -> WavSpec {
+        WavSpec { channels, sample_rate: 44100, bits_per_sample: 16, sample_format: SampleFormat::Int }
+    }
+
+    #[test]
+    fn loads_and_resamples_to_power_of_two() {
+        let samples: Vec<i16> = (0..600).map(|i| (crate::sine(i as f64 / 600.0) * 32767.0) as i16).collect();
+        let bytes = write_wav(spec(1), &samples);
+        let waveform = read_wavetable(WavReader::new(Cursor::new(bytes)).unwrap(), Interpolation::Linear).unwrap();
+        match waveform {
+            Waveform::Wavetable { table, interpolation } => {
+                assert_eq!(table.len(), 1024);
+                assert_eq!(interpolation, Interpolation::Linear);
+                assert!((table[256] - 1.0).abs() < 1e-3);
+                assert!((table[768] + 1.0).abs() < 1e-3);
+            },
+            _ => panic!("expected a wavetable"),
+        }
+    }
+
+    #[test]
+    fn rejects_stereo_and_empty_files() {
+        let stereo = write_wav(spec(2), &[0, 0, 100, 100]);
+        assert!(read_wavetable(WavReader::new(Cursor::new(stereo)).unwrap(), Interpolation::None).is_err());
+        let empty = write_wav(spec(1), &[]);
+        assert!(read_wavetable(WavReader::new(Cursor::new(empty)).unwrap(), Interpolation::None).is_err());
+    }
+}